@@ -0,0 +1,501 @@
+//! Results of the monitor lambda consumed by the reporter lambda
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Validation level of a certificate per CA/Browser Forum baseline requirements
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ValidationLevel {
+    Ev,
+    Ov,
+    Iv,
+    Dv,
+}
+
+/// Certificate of an endpoint the domain redirects to
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct RedirectTarget {
+    /// Host and port of the endpoint, like `www.example.com:443`
+    pub target: String,
+    pub days_remaining: i64,
+    pub not_after: DateTime<Utc>,
+    /// Why the endpoint could not be validated, leaving the dates unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Result of checking a single domain
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct Status {
+    pub domain: String,
+    pub valid: bool,
+    pub error: String,
+    pub error_kind: Option<String>,
+    /// Stable reason code of the failure, like `NET` or `CERT_EXPIRED`
+    pub error_code: Option<String>,
+    pub days_remaining: Option<i64>,
+    pub not_after: Option<DateTime<Utc>>,
+    pub alpn_protocol: Option<String>,
+    pub ocsp_stapled: bool,
+    /// Hours since the certificate expired, if it already has
+    pub expired_hours_ago: Option<i64>,
+    pub organization: Option<String>,
+    /// Common name or organization of the leaf certificate issuer
+    pub issuer: Option<String>,
+    /// Certificate chains through a CA about to be distrusted by browsers
+    pub distrusted_issuer: Option<String>,
+    pub validation_level: Option<ValidationLevel>,
+    /// Number of embedded Signed Certificate Timestamps of the leaf certificate
+    pub sct_count: Option<usize>,
+    /// Public key algorithm of the leaf certificate, like RSA or ECDSA
+    pub key_type: Option<String>,
+    /// Public key size of the leaf certificate in bits
+    pub key_bits: Option<u32>,
+    /// Failure is downgraded to a warning and should not raise an alert
+    pub suppressed: bool,
+    /// Importance tier of the domain from the config, like `critical` or `low`
+    pub tier: Option<String>,
+    /// SHA-256 fingerprint of the leaf certificate in hex
+    pub fingerprint: Option<String>,
+    /// Leaf certificate changed since the last check outside a maintenance window
+    pub rotated: bool,
+    /// Length of the leaf certificate validity period in days
+    pub validity_days: Option<i64>,
+    /// Leaf certificate is valid for longer than `MAX_VALIDITY_DAYS`
+    pub validity_exceeded: bool,
+    /// Renewed leaf certificate is valid for much shorter than the previous one
+    pub validity_shortened: bool,
+    /// Base64 SHA-256 hash of the leaf certificate Subject Public Key Info
+    pub spki_hash: Option<String>,
+    /// Reused from a recent healthy check instead of checking again
+    pub cached: bool,
+    /// Fatal TLS alert sent by the server after presenting its certificates
+    pub late_alert: Option<String>,
+    /// Expected names of the domain not covered by the leaf certificate
+    pub uncovered_names: Vec<String>,
+    /// Time taken to check the domain in milliseconds
+    pub duration_ms: Option<u64>,
+    /// Names of the checks that ran for a valid domain, like `expiry`, `chain` or `san`
+    pub checks_performed: Vec<String>,
+    /// Redirect targets of the HTTP probe validated along with the domain
+    pub redirects: Vec<RedirectTarget>,
+}
+
+/// Response of the monitor lambda
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq)]
+pub struct Response {
+    pub req_id: String,
+    pub statuses: Vec<Status>,
+    /// Number of domains by days remaining until expiration
+    #[serde(default)]
+    pub buckets: Vec<(String, usize)>,
+    /// Checks ran during planned maintenance, so failures are suppressed
+    #[serde(default)]
+    pub maintenance: bool,
+    /// Summary of failures sharing a single cause, like a network outage
+    #[serde(default)]
+    pub summary: Option<String>,
+    /// When the checks ran
+    #[serde(default)]
+    pub checked_at: Option<DateTime<Utc>>,
+    /// When to run the checks again, sooner if a certificate expires soon
+    #[serde(default)]
+    pub recommended_recheck_at: Option<DateTime<Utc>>,
+    /// Checks stopped at the first invalid domain, so the rest is not listed
+    #[serde(default)]
+    pub stopped_early: bool,
+    /// Failures of best-effort sinks like history or report exports
+    #[serde(default)]
+    pub sink_errors: Vec<String>,
+}
+
+/// Severity of a domain or of a whole run, used to route notifications
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Nothing to report
+    Ok,
+    /// Certificates expiring soon, except for critical tier domains,
+    /// suppressed failures and valid certificates needing attention,
+    /// like ones followed by a fatal TLS alert
+    Warning,
+    /// Expired certificates, network and validation failures
+    Critical,
+}
+
+impl Severity {
+    /// Severity of a single domain. Failures of `warning_kinds`, like
+    /// transient `dns` errors, count as warnings while staying invalid.
+    pub fn of(status: &Status, warning_kinds: &[String]) -> Severity {
+        match status.error_kind.as_deref() {
+            // Valid certificates of soon distrusted CAs need a migration,
+            // overly long-lived ones point to a misconfigured issuance
+            // and servers sending a fatal alert after them to a broken setup.
+            // Unexpected rotations may be a takeover or a botched renewal,
+            // and failing redirect targets break visitors of the domain.
+            _ if status.valid => {
+                if status.distrusted_issuer.is_some()
+                    || status.validity_exceeded
+                    || status.late_alert.is_some()
+                    || status.rotated
+                    || status.validity_shortened
+                    || status
+                        .redirects
+                        .iter()
+                        .any(|redirect| redirect.error.is_some())
+                {
+                    Severity::Warning
+                } else {
+                    Severity::Ok
+                }
+            }
+            _ if status.suppressed => Severity::Warning,
+            // Expiring certificates of critical domains alert louder
+            Some("expired") if status.tier.as_deref() != Some("critical") => Severity::Warning,
+            Some(kind) if warning_kinds.iter().any(|warning| warning == kind) => Severity::Warning,
+            _ => Severity::Critical,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Ok => "ok",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Severity {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "ok" => Ok(Severity::Ok),
+            "warning" => Ok(Severity::Warning),
+            "critical" => Ok(Severity::Critical),
+            _ => Err(format!("Unknown severity {}", value)),
+        }
+    }
+}
+
+/// Overall severity of unsuppressed statuses
+pub fn severity(statuses: &[Status], warning_kinds: &[String]) -> Severity {
+    statuses
+        .iter()
+        .filter(|status| !status.suppressed)
+        .map(|status| Severity::of(status, warning_kinds))
+        .max()
+        .unwrap_or(Severity::Ok)
+}
+
+/// Parse a comma-separated list of error kinds counted as warnings,
+/// like the `WARNING_ERROR_KINDS` setting
+pub fn parse_warning_kinds(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|kind| kind.trim().to_string())
+        .filter(|kind| !kind.is_empty())
+        .collect()
+}
+
+/// Minimal number of domains failing with the same cause to collapse them
+pub const COMMON_FAILURE_MIN_DOMAINS: usize = 5;
+
+/// Minimal fraction of all domains failing with the same cause to collapse them
+pub const COMMON_FAILURE_RATIO: f64 = 0.8;
+
+/// Error kinds of connectivity failures collapsed when shared by most domains.
+/// Certificate failures like expirations are always listed per domain,
+/// since every domain needs its own renewal.
+pub const COMMON_FAILURE_KINDS: [&str; 4] = ["network", "dns", "handshake", "circuit_open"];
+
+/// Unsuppressed failures sharing the same error kind
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommonFailure {
+    pub error_kind: String,
+    /// Error of the first failed domain as an example
+    pub error: String,
+    pub count: usize,
+}
+
+impl std::fmt::Display for CommonFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} domains: {} — likely {} outage",
+            self.count, self.error, self.error_kind
+        )
+    }
+}
+
+/// Detect when most domains failed with the same connectivity error kind, which points
+/// to an outage of the monitor itself rather than to certificate issues
+pub fn common_failure(statuses: &[Status]) -> Option<CommonFailure> {
+    let mut counts: Vec<(&str, usize, &str)> = Vec::new();
+    for status in statuses
+        .iter()
+        .filter(|status| !status.valid && !status.suppressed)
+    {
+        let Some(kind) = status
+            .error_kind
+            .as_deref()
+            .filter(|kind| COMMON_FAILURE_KINDS.contains(kind))
+        else {
+            continue;
+        };
+        match counts.iter_mut().find(|(k, _, _)| *k == kind) {
+            Some((_, count, _)) => *count += 1,
+            None => counts.push((kind, 1, &status.error)),
+        }
+    }
+    let (kind, count, error) = counts.into_iter().max_by_key(|(_, count, _)| *count)?;
+    if count >= COMMON_FAILURE_MIN_DOMAINS
+        && count as f64 >= statuses.len() as f64 * COMMON_FAILURE_RATIO
+    {
+        Some(CommonFailure {
+            error_kind: kind.to_string(),
+            error: error.to_string(),
+            count,
+        })
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_defaults() {
+        let status: Status =
+            serde_json::from_str(r#"{"domain": "example.com", "valid": false, "error": "oops"}"#)
+                .unwrap();
+        assert_eq!(
+            status,
+            Status {
+                domain: "example.com".into(),
+                error: "oops".into(),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_response_roundtrip() {
+        let response = Response {
+            req_id: "42".into(),
+            statuses: vec![Status {
+                domain: "example.com".into(),
+                valid: true,
+                days_remaining: Some(30),
+                validation_level: Some(ValidationLevel::Ev),
+                ..Default::default()
+            }],
+            buckets: vec![("30-90d".into(), 1)],
+            maintenance: false,
+            summary: None,
+            checked_at: Some("2024-05-01T00:00:00Z".parse().unwrap()),
+            recommended_recheck_at: Some("2024-05-02T00:00:00Z".parse().unwrap()),
+            stopped_early: true,
+            sink_errors: vec!["history: timed out after 1s".into()],
+        };
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["statuses"][0]["validation_level"], "EV");
+        assert_eq!(serde_json::from_value::<Response>(json).unwrap(), response);
+    }
+
+    fn failed(domain: &str, kind: &str) -> Status {
+        Status {
+            domain: domain.into(),
+            error: format!("{} error", kind),
+            error_kind: Some(kind.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_common_failure() {
+        let mut statuses: Vec<Status> = (0..9)
+            .map(|i| failed(&format!("d{}.com", i), "network"))
+            .collect();
+        statuses.push(failed("expired.com", "expired"));
+        let failure = common_failure(&statuses).expect("should collapse");
+        assert_eq!(failure.count, 9);
+        assert_eq!(failure.error_kind, "network");
+        assert_eq!(
+            failure.to_string(),
+            "9 domains: network error — likely network outage"
+        );
+    }
+
+    #[test]
+    fn test_common_failure_not_collapsed() {
+        // Too few domains
+        let statuses: Vec<Status> = (0..4)
+            .map(|i| failed(&format!("d{}.com", i), "network"))
+            .collect();
+        assert_eq!(common_failure(&statuses), None);
+
+        // Too small fraction
+        let mut statuses: Vec<Status> = (0..5)
+            .map(|i| failed(&format!("d{}.com", i), "network"))
+            .collect();
+        statuses.extend((0..5).map(|i| Status {
+            domain: format!("ok{}.com", i),
+            valid: true,
+            ..Default::default()
+        }));
+        assert_eq!(common_failure(&statuses), None);
+
+        // Suppressed failures are not counted
+        let statuses: Vec<Status> = (0..5)
+            .map(|i| Status {
+                suppressed: true,
+                ..failed(&format!("d{}.com", i), "network")
+            })
+            .collect();
+        assert_eq!(common_failure(&statuses), None);
+
+        // Expiring certificates each need a renewal, so they are all listed
+        let statuses: Vec<Status> = (0..5)
+            .map(|i| failed(&format!("d{}.com", i), "expired"))
+            .collect();
+        assert_eq!(common_failure(&statuses), None);
+    }
+
+    #[test]
+    fn test_severity() {
+        let status = |valid, error_kind: Option<&str>, suppressed| Status {
+            domain: "foobar".into(),
+            valid,
+            error_kind: error_kind.map(String::from),
+            suppressed,
+            ..Default::default()
+        };
+        let valid = status(true, None, false);
+        let expiring = status(false, Some("expired"), false);
+        let expired = status(false, Some("expired_ago"), false);
+        let network = status(false, Some("network"), false);
+        let suppressed = status(false, Some("network"), true);
+
+        // Suppressed failures are warnings of their own, but do not raise a run
+        assert_eq!(Severity::of(&suppressed, &[]), Severity::Warning);
+        assert_eq!(Severity::of(&network, &[]), Severity::Critical);
+        assert_eq!(severity(&[], &[]), Severity::Ok);
+        assert_eq!(severity(std::slice::from_ref(&valid), &[]), Severity::Ok);
+        assert_eq!(
+            severity(&[valid.clone(), suppressed.clone()], &[]),
+            Severity::Ok
+        );
+        assert_eq!(
+            severity(&[valid.clone(), expiring.clone()], &[]),
+            Severity::Warning
+        );
+        assert_eq!(
+            severity(&[expiring.clone(), suppressed], &[]),
+            Severity::Warning
+        );
+        assert_eq!(
+            severity(&[expiring.clone(), expired], &[]),
+            Severity::Critical
+        );
+        assert_eq!(
+            severity(&[valid.clone(), expiring.clone(), network], &[]),
+            Severity::Critical
+        );
+        let distrusted = Status {
+            distrusted_issuer: Some("symantec".into()),
+            ..valid.clone()
+        };
+        assert_eq!(
+            severity(&[valid.clone(), distrusted], &[]),
+            Severity::Warning
+        );
+        let long_lived = Status {
+            validity_exceeded: true,
+            ..valid.clone()
+        };
+        assert_eq!(
+            severity(&[valid.clone(), long_lived], &[]),
+            Severity::Warning
+        );
+        let late_alert = Status {
+            late_alert: Some("received fatal alert: InternalError".into()),
+            ..valid.clone()
+        };
+        assert_eq!(Severity::of(&late_alert, &[]), Severity::Warning);
+        assert_eq!(
+            severity(&[valid.clone(), late_alert], &[]),
+            Severity::Warning
+        );
+        let rotated = Status {
+            rotated: true,
+            ..valid.clone()
+        };
+        assert_eq!(Severity::of(&rotated, &[]), Severity::Warning);
+        let shortened = Status {
+            validity_shortened: true,
+            ..valid
+        };
+        assert_eq!(Severity::of(&shortened, &[]), Severity::Warning);
+        let redirect = |error: Option<&str>| Status {
+            redirects: vec![RedirectTarget {
+                target: "www.example.com:443".into(),
+                error: error.map(String::from),
+                ..Default::default()
+            }],
+            ..status(true, None, false)
+        };
+        assert_eq!(Severity::of(&redirect(None), &[]), Severity::Ok);
+        assert_eq!(
+            Severity::of(&redirect(Some("certificate expires in 3 days")), &[]),
+            Severity::Warning
+        );
+    }
+
+    #[test]
+    fn test_severity_critical_tier() {
+        let expiring = |tier: Option<&str>| Status {
+            domain: "foobar".into(),
+            valid: false,
+            error_kind: Some("expired".into()),
+            tier: tier.map(String::from),
+            ..Default::default()
+        };
+        assert_eq!(severity(&[expiring(Some("low"))], &[]), Severity::Warning);
+        assert_eq!(severity(&[expiring(None)], &[]), Severity::Warning);
+        assert_eq!(
+            severity(&[expiring(Some("critical"))], &[]),
+            Severity::Critical
+        );
+    }
+
+    #[test]
+    fn test_parse_severity() {
+        assert_eq!("ok".parse(), Ok(Severity::Ok));
+        assert_eq!("warning".parse(), Ok(Severity::Warning));
+        assert_eq!("critical".parse(), Ok(Severity::Critical));
+        assert!("fatal".parse::<Severity>().is_err());
+        assert!(Severity::Warning < Severity::Critical);
+    }
+
+    #[test]
+    fn test_parse_warning_kinds() {
+        assert_eq!(
+            parse_warning_kinds("dns, network,,"),
+            vec!["dns", "network"]
+        );
+        assert!(parse_warning_kinds(" ").is_empty());
+    }
+}
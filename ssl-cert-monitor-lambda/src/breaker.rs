@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Per-host circuit breaker skipping hosts that keep failing to connect
+pub struct CircuitBreaker {
+    threshold: u32,
+    failures: Mutex<HashMap<String, u32>>,
+}
+
+impl CircuitBreaker {
+    /// Open the circuit for a host after `threshold` consecutive failures
+    pub fn new(threshold: u32) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Failure counters, still usable if a thread panicked while holding the
+    /// lock, since every update leaves them consistent
+    fn failures(&self) -> MutexGuard<'_, HashMap<String, u32>> {
+        self.failures.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+
+    /// Whether further attempts to the host should be skipped
+    pub fn is_open(&self, host: &str) -> bool {
+        self.failures()
+            .get(host)
+            .is_some_and(|&failures| failures >= self.threshold)
+    }
+
+    pub fn record_failure(&self, host: &str) {
+        *self.failures().entry(host.to_string()).or_default() += 1;
+    }
+
+    pub fn record_success(&self, host: &str) {
+        self.failures().remove(host);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breaker_opens_after_threshold() {
+        let breaker = CircuitBreaker::new(2);
+        assert!(!breaker.is_open("a.com"));
+        breaker.record_failure("a.com");
+        assert!(!breaker.is_open("a.com"));
+        breaker.record_failure("a.com");
+        assert!(breaker.is_open("a.com"));
+        // Other hosts are not affected
+        assert!(!breaker.is_open("b.com"));
+    }
+
+    #[test]
+    fn test_breaker_resets_on_success() {
+        let breaker = CircuitBreaker::new(2);
+        breaker.record_failure("a.com");
+        breaker.record_success("a.com");
+        breaker.record_failure("a.com");
+        assert!(!breaker.is_open("a.com"));
+    }
+
+    #[test]
+    fn test_breaker_recovers_from_poisoning() {
+        let breaker = CircuitBreaker::new(2);
+        breaker.record_failure("a.com");
+        std::thread::scope(|scope| {
+            let poisoner = scope.spawn(|| {
+                let _failures = breaker.failures.lock().unwrap();
+                panic!("validation panicked");
+            });
+            assert!(poisoner.join().is_err());
+        });
+        assert!(breaker.failures.is_poisoned());
+        breaker.record_failure("a.com");
+        assert!(breaker.is_open("a.com"));
+        breaker.record_success("a.com");
+        assert!(!breaker.is_open("a.com"));
+    }
+
+    #[test]
+    fn test_breaker_zero_threshold() {
+        let breaker = CircuitBreaker::new(0);
+        assert_eq!(breaker.threshold(), 1);
+        assert!(!breaker.is_open("a.com"));
+        breaker.record_failure("a.com");
+        assert!(breaker.is_open("a.com"));
+    }
+}
@@ -0,0 +1,258 @@
+use crate::error::MonitorError;
+use crate::{check_all, expand_domains, output, warning_kinds};
+use chrono::Utc;
+use lambda_runtime::Error;
+use ssl_cert_monitor_common::{severity, Severity, Status};
+
+/// Output format of a local run
+#[derive(Debug, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+    Prometheus,
+    Csv,
+}
+
+impl std::str::FromStr for Format {
+    type Err = MonitorError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "prometheus" => Ok(Format::Prometheus),
+            "csv" => Ok(Format::Csv),
+            _ => Err(MonitorError::Config(format!("Unknown format {}", value))),
+        }
+    }
+}
+
+/// Least severe result failing a local run, e.g. for CI gating
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailOn {
+    /// Expiring certificates and other warnings fail the run
+    Warning,
+    /// Only expired certificates and other failures fail the run
+    Critical,
+    /// Always succeed
+    Never,
+}
+
+impl std::str::FromStr for FailOn {
+    type Err = MonitorError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "warning" => Ok(FailOn::Warning),
+            "critical" => Ok(FailOn::Critical),
+            "never" => Ok(FailOn::Never),
+            _ => Err(MonitorError::Config(format!("Unknown fail-on {}", value))),
+        }
+    }
+}
+
+/// Process exit code, non-zero if any unsuppressed status is at least
+/// as severe as `fail_on`
+pub fn exit_code(statuses: &[Status], fail_on: FailOn, warning_kinds: &[String]) -> i32 {
+    let threshold = match fail_on {
+        FailOn::Warning => Severity::Warning,
+        FailOn::Critical => Severity::Critical,
+        FailOn::Never => return 0,
+    };
+    i32::from(severity(statuses, warning_kinds) >= threshold)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CliArgs {
+    pub format: Format,
+    pub fail_on: FailOn,
+    pub domains: Vec<String>,
+}
+
+/// Parse `[--format text|json|prometheus|csv] [--fail-on warning|critical|never] DOMAIN...`
+pub fn parse_args(args: &[String]) -> Result<CliArgs, MonitorError> {
+    let mut format = Format::Text;
+    let mut fail_on = FailOn::Warning;
+    let mut domains = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if let Some(value) = arg.strip_prefix("--format=") {
+            format = value.parse()?;
+        } else if arg == "--format" {
+            format = args
+                .next()
+                .ok_or(MonitorError::Config("Missing format".into()))?
+                .parse()?;
+        } else if let Some(value) = arg.strip_prefix("--fail-on=") {
+            fail_on = value.parse()?;
+        } else if arg == "--fail-on" {
+            fail_on = args
+                .next()
+                .ok_or(MonitorError::Config("Missing fail-on".into()))?
+                .parse()?;
+        } else if arg.starts_with("--") {
+            return Err(MonitorError::Config(format!("Unknown option {}", arg)));
+        } else {
+            domains.push(arg.clone());
+        }
+    }
+    if domains.is_empty() {
+        return Err(MonitorError::Config("No domains to check".into()));
+    }
+    Ok(CliArgs {
+        format,
+        fail_on,
+        domains,
+    })
+}
+
+/// Check domains from command line and print results.
+/// Returns a process exit code selected by `--fail-on`.
+pub async fn run(args: &[String]) -> Result<i32, Error> {
+    let args = parse_args(args)?;
+
+    let domains = expand_domains(args.domains);
+    let (statuses, _) = check_all(domains, Utc::now(), None, None, false).await?;
+    let warning_kinds = warning_kinds();
+
+    match args.format {
+        Format::Text => print!("{}", output::render_text(&statuses)),
+        Format::Json => println!("{}", output::render_json(&statuses)?),
+        Format::Prometheus => print!("{}", output::render_prometheus(&statuses)),
+        Format::Csv => print!("{}", output::render_csv(&statuses, &warning_kinds)),
+    }
+
+    Ok(exit_code(&statuses, args.fail_on, &warning_kinds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            parse_args(&args(&["a.com", "b.com"])).unwrap(),
+            CliArgs {
+                format: Format::Text,
+                fail_on: FailOn::Warning,
+                domains: args(&["a.com", "b.com"]),
+            }
+        );
+        assert_eq!(
+            parse_args(&args(&["--format", "json", "a.com"]))
+                .unwrap()
+                .format,
+            Format::Json
+        );
+        assert_eq!(
+            parse_args(&args(&["a.com", "--format=prometheus"]))
+                .unwrap()
+                .format,
+            Format::Prometheus
+        );
+        assert_eq!(
+            parse_args(&args(&["--format=csv", "a.com"]))
+                .unwrap()
+                .format,
+            Format::Csv
+        );
+        assert_eq!(
+            parse_args(&args(&["--fail-on", "critical", "a.com"]))
+                .unwrap()
+                .fail_on,
+            FailOn::Critical
+        );
+        assert_eq!(
+            parse_args(&args(&["a.com", "--fail-on=never"]))
+                .unwrap()
+                .fail_on,
+            FailOn::Never
+        );
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let valid = Status {
+            domain: "valid.com".into(),
+            valid: true,
+            ..Default::default()
+        };
+        let expiring = Status {
+            domain: "expiring.com".into(),
+            valid: false,
+            error_kind: Some("expired".into()),
+            ..Default::default()
+        };
+        let broken = Status {
+            domain: "broken.com".into(),
+            valid: false,
+            error_kind: Some("network".into()),
+            ..Default::default()
+        };
+        let suppressed = Status {
+            suppressed: true,
+            ..broken.clone()
+        };
+
+        // Expiring certificates of critical domains fail like in the reporter
+        let critical_tier = Status {
+            tier: Some("critical".into()),
+            ..expiring.clone()
+        };
+        assert_eq!(exit_code(&[critical_tier], FailOn::Critical, &[]), 1);
+        assert_eq!(
+            exit_code(
+                std::slice::from_ref(&broken),
+                FailOn::Critical,
+                &["network".into()]
+            ),
+            0
+        );
+
+        let healthy = [valid.clone(), suppressed];
+        let warnings = [valid.clone(), expiring.clone()];
+        let criticals = [valid, expiring, broken];
+        for (statuses, fail_on, code) in [
+            (&healthy[..], FailOn::Warning, 0),
+            (&healthy[..], FailOn::Critical, 0),
+            (&warnings[..], FailOn::Warning, 1),
+            (&warnings[..], FailOn::Critical, 0),
+            (&warnings[..], FailOn::Never, 0),
+            (&criticals[..], FailOn::Warning, 1),
+            (&criticals[..], FailOn::Critical, 1),
+            (&criticals[..], FailOn::Never, 0),
+        ] {
+            assert_eq!(
+                exit_code(statuses, fail_on, &[]),
+                code,
+                "{:?} {:?}",
+                statuses,
+                fail_on
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_args_wrong() {
+        for wrong in [
+            args(&[]),
+            args(&["--format", "json"]),
+            args(&["a.com", "--format"]),
+            args(&["a.com", "--format", "xml"]),
+            args(&["a.com", "--verbose"]),
+            args(&["a.com", "--fail-on"]),
+            args(&["a.com", "--fail-on", "error"]),
+        ] {
+            assert!(
+                matches!(parse_args(&wrong), Err(MonitorError::Config(_))),
+                "{:?}",
+                wrong
+            );
+        }
+    }
+}
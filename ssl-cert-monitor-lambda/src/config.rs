@@ -0,0 +1,124 @@
+use crate::error::MonitorError;
+use serde::Deserialize;
+use ssl_cert_monitor_common::Status;
+
+/// Domain entry of a structured config line
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonEntry {
+    Domain(String),
+    Entry {
+        domain: String,
+        #[serde(default)]
+        alt: Vec<String>,
+        #[serde(default)]
+        tier: Option<String>,
+        #[serde(default)]
+        pins: Vec<String>,
+        #[serde(default)]
+        ports: Vec<u16>,
+    },
+}
+
+impl JsonEntry {
+    /// Convert to the plain config line format
+    fn into_line(self) -> String {
+        match self {
+            JsonEntry::Domain(domain) => domain,
+            JsonEntry::Entry {
+                domain,
+                alt,
+                tier,
+                pins,
+                ports,
+            } => {
+                let mut line = domain;
+                if !alt.is_empty() {
+                    line += &format!(" alt={}", alt.join(","));
+                }
+                if let Some(tier) = tier {
+                    line += &format!(" tier={}", tier);
+                }
+                if !pins.is_empty() {
+                    line += &format!(" pin={}", pins.join(","));
+                }
+                if !ports.is_empty() {
+                    let ports: Vec<String> = ports.iter().map(u16::to_string).collect();
+                    line += &format!(" ports={}", ports.join(","));
+                }
+                line
+            }
+        }
+    }
+}
+
+/// Whether the config is structured, with a JSON value per line
+pub fn is_structured(location: &str) -> bool {
+    location.ends_with(".json") || location.ends_with(".jsonl")
+}
+
+/// Parse structured config lines leniently: malformed lines are reported
+/// as failed statuses while the rest is still checked
+pub fn parse_structured(lines: Vec<String>) -> (Vec<String>, Vec<Status>) {
+    let mut domains = Vec::new();
+    let mut errors = Vec::new();
+    for (index, line) in lines.into_iter().enumerate() {
+        match serde_json::from_str::<JsonEntry>(&line) {
+            Ok(entry) => domains.push(entry.into_line()),
+            Err(err) => {
+                let error = MonitorError::Config(format!("line {}: {}", index + 1, err));
+                errors.push(Status {
+                    domain: line,
+                    valid: false,
+                    error: error.to_string(),
+                    error_kind: Some(error.kind().to_string()),
+                    error_code: Some(error.code().to_string()),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+    (domains, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_structured() {
+        assert!(is_structured("s3://bucket/config.json"));
+        assert!(is_structured("s3://bucket/config.jsonl"));
+        assert!(!is_structured("s3://bucket/config.txt"));
+    }
+
+    #[test]
+    fn test_parse_structured_partial() {
+        let lines = vec![
+            r#""a.com""#.to_string(),
+            r#"{"domain": "b.com", "alt": ["www.b.com", "api.b.com"]}"#.to_string(),
+            r#"{"domain": "c.com""#.to_string(),
+            r#"{"domain": "d.com"}"#.to_string(),
+            r#"{"domain": "e.com", "tier": "critical"}"#.to_string(),
+            r#"{"domain": "f.com", "pins": ["aa=", "bb="]}"#.to_string(),
+            r#"{"domain": "g.com", "ports": [443, 8443]}"#.to_string(),
+        ];
+        let (domains, errors) = parse_structured(lines);
+        assert_eq!(
+            domains,
+            vec![
+                "a.com",
+                "b.com alt=www.b.com,api.b.com",
+                "d.com",
+                "e.com tier=critical",
+                "f.com pin=aa=,bb=",
+                "g.com ports=443,8443"
+            ]
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].domain, r#"{"domain": "c.com""#);
+        assert!(!errors[0].valid);
+        assert_eq!(errors[0].error_kind.as_deref(), Some("config"));
+        assert!(errors[0].error.contains("line 3"), "{}", errors[0].error);
+    }
+}
@@ -0,0 +1,279 @@
+use crate::cert::Transport;
+use crate::error::MonitorError;
+use lambda_runtime::tracing::info;
+use rustls::pki_types::CertificateDer;
+
+/// TLSA record publishing the certificate of a service (RFC 6698)
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsaRecord {
+    /// 0 PKIX-TA, 1 PKIX-EE, 2 DANE-TA, 3 DANE-EE
+    pub usage: u8,
+    /// 0 full certificate, 1 Subject Public Key Info
+    pub selector: u8,
+    /// 0 exact content, 1 SHA-256, 2 SHA-512
+    pub matching: u8,
+    pub data: Vec<u8>,
+}
+
+impl std::str::FromStr for TlsaRecord {
+    type Err = MonitorError;
+
+    /// Parse the presentation format, like `3 1 1 0C72AC70...`
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let wrong_record = || MonitorError::Config(format!("Wrong TLSA record {}", value));
+        let mut parts = value.split_whitespace();
+        let mut field = || -> Result<u8, MonitorError> {
+            parts
+                .next()
+                .and_then(|part| part.parse().ok())
+                .ok_or_else(wrong_record)
+        };
+        let (usage, selector, matching) = (field()?, field()?, field()?);
+        let hex: String = parts.collect();
+        if hex.is_empty() || !hex.len().is_multiple_of(2) {
+            return Err(wrong_record());
+        }
+        let data = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(wrong_record)?;
+        Ok(TlsaRecord {
+            usage,
+            selector,
+            matching,
+            data,
+        })
+    }
+}
+
+impl From<&hickory_resolver::proto::rr::rdata::TLSA> for TlsaRecord {
+    fn from(tlsa: &hickory_resolver::proto::rr::rdata::TLSA) -> Self {
+        TlsaRecord {
+            usage: tlsa.cert_usage().into(),
+            selector: tlsa.selector().into(),
+            matching: tlsa.matching().into(),
+            data: tlsa.cert_data().to_vec(),
+        }
+    }
+}
+
+/// Owner name of TLSA records of a service, like `_443._tcp.example.com`,
+/// or `_443._udp.example.com` for QUIC
+pub fn tlsa_name(host: &str, port: u16, transport: Transport) -> String {
+    let protocol = match transport {
+        Transport::Tcp => "tcp",
+        Transport::Quic => "udp",
+    };
+    format!("_{}._{}.{}", port, protocol, host)
+}
+
+/// Content of the certificate selected by the record, `None` for unknown selectors
+fn selected(record: &TlsaRecord, cert: &CertificateDer<'_>) -> Option<Vec<u8>> {
+    match record.selector {
+        0 => Some(cert.to_vec()),
+        1 => webpki::EndEntityCert::try_from(cert)
+            .ok()
+            .map(|cert| cert.subject_public_key_info().as_ref().to_vec()),
+        _ => None,
+    }
+}
+
+/// Whether the record matches a certificate, `None` for records with unknown
+/// selector or matching type, which are unusable
+fn matches_certificate(record: &TlsaRecord, cert: &CertificateDer<'_>) -> Option<bool> {
+    let content = selected(record, cert)?;
+    let associated = match record.matching {
+        0 => content,
+        1 => aws_lc_rs::digest::digest(&aws_lc_rs::digest::SHA256, &content)
+            .as_ref()
+            .to_vec(),
+        2 => aws_lc_rs::digest::digest(&aws_lc_rs::digest::SHA512, &content)
+            .as_ref()
+            .to_vec(),
+        _ => return None,
+    };
+    Some(associated == record.data)
+}
+
+/// Whether the record matches the presented chain: end-entity usages match
+/// the leaf, trust anchor usages match any certificate above it.
+/// Returns `None` for unusable records.
+pub fn matches_chain(record: &TlsaRecord, chain: &[CertificateDer<'_>]) -> Option<bool> {
+    let candidates = match record.usage {
+        1 | 3 => chain.get(..1)?,
+        0 | 2 => chain.get(1..)?,
+        _ => return None,
+    };
+    let mut usable = false;
+    for cert in candidates {
+        match matches_certificate(record, cert) {
+            Some(true) => return Some(true),
+            Some(false) => usable = true,
+            None => {}
+        }
+    }
+    // Unusable records never match any certificate
+    usable.then_some(false)
+}
+
+/// Check the presented chain against TLSA records of `name`. Domains without
+/// usable records are not checked, as DANE is not deployed for them.
+pub fn check_dane(
+    name: &str,
+    records: &[TlsaRecord],
+    chain: &[CertificateDer<'_>],
+) -> Result<(), MonitorError> {
+    let results: Vec<bool> = records
+        .iter()
+        .filter_map(|record| matches_chain(record, chain))
+        .collect();
+    if results.is_empty() {
+        info!("No usable TLSA records for {}", name);
+        return Ok(());
+    }
+    if !results.contains(&true) {
+        return Err(MonitorError::Certificate(format!(
+            "Certificate matches no TLSA record of {}",
+            name
+        )));
+    }
+    info!("Certificate matches TLSA records of {}", name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// SHA-256 of the full cert-2031.der certificate
+    const CERT_SHA256: &str = "973a41276ffd01e027a2aad49e34c37846d3e976ff6a620b6712e33832041aa6";
+
+    fn leaf() -> CertificateDer<'static> {
+        CertificateDer::from(Vec::<u8>::from(include_bytes!("./data/cert-2031.der")))
+    }
+
+    fn other() -> CertificateDer<'static> {
+        CertificateDer::from(Vec::<u8>::from(include_bytes!("./data/cert-expired.der")))
+    }
+
+    fn record(value: &str) -> TlsaRecord {
+        value.parse().expect(value)
+    }
+
+    #[test]
+    fn test_parse_tlsa_record() {
+        assert_eq!(
+            record("3 1 1 0C72AC70 b745ac19"),
+            TlsaRecord {
+                usage: 3,
+                selector: 1,
+                matching: 1,
+                data: vec![0x0c, 0x72, 0xac, 0x70, 0xb7, 0x45, 0xac, 0x19],
+            }
+        );
+        for value in ["", "3 1 1", "3 1 x 00", "3 1 1 0", "3 1 1 zz", "300 1 1 00"] {
+            assert!(
+                matches!(value.parse::<TlsaRecord>(), Err(MonitorError::Config(_))),
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_tlsa_name() {
+        assert_eq!(
+            tlsa_name("example.com", 443, Transport::Tcp),
+            "_443._tcp.example.com"
+        );
+        assert_eq!(
+            tlsa_name("example.com", 8443, Transport::Tcp),
+            "_8443._tcp.example.com"
+        );
+        assert_eq!(
+            tlsa_name("example.com", 443, Transport::Quic),
+            "_443._udp.example.com"
+        );
+    }
+
+    #[test]
+    fn test_matches_chain() {
+        use base64::Engine;
+        let spki_sha256 = base64::engine::general_purpose::STANDARD
+            .decode("8Rw90Ej3Ttt8RRkrg+WYDS9n7IS03bk5bjP/UXPtaY8=")
+            .unwrap();
+        let spki_sha256: String = spki_sha256.iter().map(|b| format!("{:02x}", b)).collect();
+        let cert_sha512: String =
+            aws_lc_rs::digest::digest(&aws_lc_rs::digest::SHA512, leaf().as_ref())
+                .as_ref()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect();
+        let cert_raw: String = leaf().iter().map(|b| format!("{:02x}", b)).collect();
+
+        let chain = [leaf()];
+        for value in [
+            format!("3 0 1 {}", CERT_SHA256),
+            format!("3 1 1 {}", spki_sha256),
+            format!("3 0 2 {}", cert_sha512),
+            format!("3 0 0 {}", cert_raw),
+            format!("1 1 1 {}", spki_sha256),
+        ] {
+            assert_eq!(
+                matches_chain(&record(&value), &chain),
+                Some(true),
+                "{}",
+                value
+            );
+        }
+
+        // Hash of the whole certificate does not match the key and vice versa
+        assert_eq!(
+            matches_chain(&record(&format!("3 1 1 {}", CERT_SHA256)), &chain),
+            Some(false)
+        );
+        assert_eq!(
+            matches_chain(&record(&format!("3 0 1 {}", spki_sha256)), &chain),
+            Some(false)
+        );
+        assert_eq!(
+            matches_chain(&record(&format!("3 0 1 {}", CERT_SHA256)), &[other()]),
+            Some(false)
+        );
+
+        // Trust anchor usages match certificates above the leaf only
+        let ta = record(&format!("2 0 1 {}", CERT_SHA256));
+        assert_eq!(matches_chain(&ta, &[other(), leaf()]), Some(true));
+        assert_eq!(matches_chain(&ta, &[leaf(), other()]), Some(false));
+        assert_eq!(matches_chain(&ta, &[leaf()]), None);
+
+        // Unknown usages, selectors and matching types are unusable
+        for value in [
+            format!("4 0 1 {}", CERT_SHA256),
+            format!("3 2 1 {}", CERT_SHA256),
+            format!("3 0 3 {}", CERT_SHA256),
+        ] {
+            assert_eq!(matches_chain(&record(&value), &chain), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_check_dane() {
+        let name = "_443._tcp.example.com";
+        let matching = record(&format!("3 0 1 {}", CERT_SHA256));
+        let mismatching = record("3 1 1 00112233");
+        let unusable = record("3 0 9 00112233");
+        let chain = [leaf()];
+
+        assert!(check_dane(name, &[], &chain).is_ok());
+        assert!(check_dane(name, std::slice::from_ref(&unusable), &chain).is_ok());
+        assert!(check_dane(name, &[mismatching.clone(), matching], &chain).is_ok());
+        match check_dane(name, &[mismatching, unusable], &chain) {
+            Err(MonitorError::Certificate(message)) => {
+                assert!(message.contains(name), "{}", message)
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use aws_sdk_dynamodb::types::{AttributeValue, KeysAndAttributes, PutRequest, WriteRequest};
+use lambda_runtime::Error;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// DynamoDB item by attribute name
+pub type Item = HashMap<String, AttributeValue>;
+
+/// Keys read by a single BatchGetItem request, the DynamoDB limit
+const BATCH_GET_KEYS: usize = 100;
+
+/// Items written by a single BatchWriteItem request, the DynamoDB limit
+const BATCH_WRITE_ITEMS: usize = 25;
+
+/// Requests sent for a batch while DynamoDB leaves some of it unprocessed
+const BATCH_ATTEMPTS: u32 = 3;
+
+/// Delay before resending unprocessed keys or items, growing with every attempt
+const BATCH_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Read items of tables with a `domain` partition key in batches.
+/// Domains without an item are left out.
+pub async fn batch_get(
+    client: &aws_sdk_dynamodb::Client,
+    table: &str,
+    domains: &[String],
+) -> Result<Vec<Item>, Error> {
+    // Duplicate keys fail the whole request
+    let mut domains = domains.to_vec();
+    domains.sort();
+    domains.dedup();
+    let mut items = Vec::with_capacity(domains.len());
+    for chunk in domains.chunks(BATCH_GET_KEYS) {
+        let keys = chunk
+            .iter()
+            .map(|domain| Item::from([("domain".to_string(), AttributeValue::S(domain.clone()))]))
+            .collect();
+        let mut pending = Some(
+            KeysAndAttributes::builder()
+                .set_keys(Some(keys))
+                .build()
+                .map_err(Box::new)?,
+        );
+        for attempt in 0..BATCH_ATTEMPTS {
+            let Some(keys) = pending.take() else {
+                break;
+            };
+            if attempt > 0 {
+                tokio::time::sleep(BATCH_BACKOFF * attempt).await;
+            }
+            let output = client
+                .batch_get_item()
+                .request_items(table, keys)
+                .send()
+                .await
+                .map_err(Box::new)?;
+            items.extend(
+                output
+                    .responses
+                    .and_then(|mut responses| responses.remove(table))
+                    .unwrap_or_default(),
+            );
+            pending = output
+                .unprocessed_keys
+                .and_then(|mut unprocessed| unprocessed.remove(table))
+                .filter(|keys| !keys.keys.is_empty());
+        }
+        if let Some(keys) = pending {
+            return Err(format!("{} keys of {} left unprocessed", keys.keys.len(), table).into());
+        }
+    }
+    Ok(items)
+}
+
+/// Write items in batches
+pub async fn batch_put(
+    client: &aws_sdk_dynamodb::Client,
+    table: &str,
+    items: Vec<Item>,
+) -> Result<(), Error> {
+    for chunk in items.chunks(BATCH_WRITE_ITEMS) {
+        let mut pending = chunk
+            .iter()
+            .map(|item| {
+                let request = PutRequest::builder().set_item(Some(item.clone())).build()?;
+                Ok(WriteRequest::builder().put_request(request).build())
+            })
+            .collect::<Result<Vec<_>, aws_sdk_dynamodb::error::BuildError>>()
+            .map_err(Box::new)?;
+        for attempt in 0..BATCH_ATTEMPTS {
+            if pending.is_empty() {
+                break;
+            }
+            if attempt > 0 {
+                tokio::time::sleep(BATCH_BACKOFF * attempt).await;
+            }
+            let output = client
+                .batch_write_item()
+                .request_items(table, std::mem::take(&mut pending))
+                .send()
+                .await
+                .map_err(Box::new)?;
+            pending = output
+                .unprocessed_items
+                .and_then(|mut unprocessed| unprocessed.remove(table))
+                .unwrap_or_default();
+        }
+        if !pending.is_empty() {
+            return Err(format!("{} items of {} left unprocessed", pending.len(), table).into());
+        }
+    }
+    Ok(())
+}
@@ -2,14 +2,193 @@
 pub enum MonitorError {
     #[error("network error: {0}")]
     Network(std::io::Error),
+    #[error("DNS error: {0}")]
+    Dns(std::io::Error),
+    #[error("connection closed before TLS handshake completed: {0}")]
+    HandshakeIncomplete(std::io::Error),
     #[error("TLS error: {0}")]
     Tls(rustls::Error),
+    #[error("no TLS parameters shared with the server: {0}")]
+    NoSharedParameters(rustls::Error),
+    #[error("skipped after {0} failed connections")]
+    CircuitOpen(u32),
     #[error("certificate error: {0}")]
     Certificate(String),
+    #[error("certificate does not cover {}", .0.join(", "))]
+    NamesNotCovered(Vec<String>),
     #[error("config error: {0}")]
     Config(String),
-    #[error("certificate expired")]
-    Expired,
+    #[error("certificate expires in {0} days")]
+    Expired(i64),
+    #[error("certificate expired {0} hours ago")]
+    ExpiredAgo(i64),
     #[error("general error: {0}")]
     General(String),
 }
+
+impl MonitorError {
+    /// Short machine-readable kind of the error
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MonitorError::Network(_) => "network",
+            MonitorError::Dns(_) => "dns",
+            MonitorError::HandshakeIncomplete(_) => "handshake",
+            MonitorError::Tls(_) => "tls",
+            MonitorError::NoSharedParameters(_) => "tls_parameters",
+            MonitorError::CircuitOpen(_) => "circuit_open",
+            MonitorError::Certificate(_) | MonitorError::NamesNotCovered(_) => "certificate",
+            MonitorError::Config(_) => "config",
+            MonitorError::Expired(_) => "expired",
+            MonitorError::ExpiredAgo(_) => "expired_ago",
+            MonitorError::General(_) => "general",
+        }
+    }
+
+    /// Stable reason code of the error for dashboards and runbooks,
+    /// independent of the message text
+    pub fn code(&self) -> &'static str {
+        match self {
+            MonitorError::Network(err) => network_code(err),
+            MonitorError::Dns(_) => "DNS",
+            MonitorError::HandshakeIncomplete(_) => "TLS_HANDSHAKE",
+            MonitorError::Tls(_) => "TLS",
+            MonitorError::NoSharedParameters(_) => "TLS_PARAMETERS",
+            MonitorError::CircuitOpen(_) => "CIRCUIT_OPEN",
+            MonitorError::Certificate(_) => "CERT_INVALID",
+            MonitorError::NamesNotCovered(_) => "CERT_NAME_MISMATCH",
+            MonitorError::Config(_) => "CONFIG",
+            MonitorError::Expired(_) => "CERT_EXPIRING",
+            MonitorError::ExpiredAgo(_) => "CERT_EXPIRED",
+            MonitorError::General(_) => "GENERAL",
+        }
+    }
+}
+
+/// Reason code of a connection failure, telling a firewall rejecting
+/// connections from an overloaded server or an unreachable host
+fn network_code(err: &std::io::Error) -> &'static str {
+    use std::io::ErrorKind;
+    match err.kind() {
+        ErrorKind::ConnectionRefused => "NET_REFUSED",
+        ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted => "NET_RESET",
+        // Socket read timeouts are reported as `WouldBlock` on Unix
+        ErrorKind::TimedOut | ErrorKind::WouldBlock => "NET_TIMEOUT",
+        ErrorKind::HostUnreachable | ErrorKind::NetworkUnreachable => "NET_UNREACHABLE",
+        _ => "NET",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kind() {
+        let cases = vec![
+            (
+                MonitorError::Network(std::io::Error::from(std::io::ErrorKind::TimedOut)),
+                "network",
+            ),
+            (
+                MonitorError::Dns(std::io::Error::from(std::io::ErrorKind::NotFound)),
+                "dns",
+            ),
+            (
+                MonitorError::HandshakeIncomplete(std::io::Error::from(
+                    std::io::ErrorKind::UnexpectedEof,
+                )),
+                "handshake",
+            ),
+            (
+                MonitorError::Tls(rustls::Error::HandshakeNotComplete),
+                "tls",
+            ),
+            (
+                MonitorError::NoSharedParameters(rustls::Error::PeerIncompatible(
+                    rustls::PeerIncompatible::NoCipherSuitesInCommon,
+                )),
+                "tls_parameters",
+            ),
+            (MonitorError::CircuitOpen(3), "circuit_open"),
+            (MonitorError::Certificate("bad".into()), "certificate"),
+            (
+                MonitorError::NamesNotCovered(vec!["a.example.com".into()]),
+                "certificate",
+            ),
+            (MonitorError::Config("bad".into()), "config"),
+            (MonitorError::Expired(3), "expired"),
+            (MonitorError::ExpiredAgo(3), "expired_ago"),
+            (MonitorError::General("bad".into()), "general"),
+        ];
+        for (error, kind) in cases {
+            assert_eq!(error.kind(), kind, "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_code() {
+        let cases = vec![
+            (MonitorError::Network(std::io::Error::other("oops")), "NET"),
+            (
+                MonitorError::Dns(std::io::Error::from(std::io::ErrorKind::NotFound)),
+                "DNS",
+            ),
+            (
+                MonitorError::HandshakeIncomplete(std::io::Error::from(
+                    std::io::ErrorKind::UnexpectedEof,
+                )),
+                "TLS_HANDSHAKE",
+            ),
+            (
+                MonitorError::Tls(rustls::Error::HandshakeNotComplete),
+                "TLS",
+            ),
+            (
+                MonitorError::NoSharedParameters(rustls::Error::PeerIncompatible(
+                    rustls::PeerIncompatible::NoCipherSuitesInCommon,
+                )),
+                "TLS_PARAMETERS",
+            ),
+            (MonitorError::CircuitOpen(3), "CIRCUIT_OPEN"),
+            (MonitorError::Certificate("bad".into()), "CERT_INVALID"),
+            (
+                MonitorError::NamesNotCovered(vec!["a.example.com".into()]),
+                "CERT_NAME_MISMATCH",
+            ),
+            (MonitorError::Config("bad".into()), "CONFIG"),
+            (MonitorError::Expired(3), "CERT_EXPIRING"),
+            (MonitorError::ExpiredAgo(3), "CERT_EXPIRED"),
+            (MonitorError::General("bad".into()), "GENERAL"),
+        ];
+        for (error, code) in cases {
+            assert_eq!(error.code(), code, "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_network_code() {
+        use std::io::{Error, ErrorKind};
+        for (error, code) in [
+            (Error::from(ErrorKind::ConnectionRefused), "NET_REFUSED"),
+            (Error::from(ErrorKind::ConnectionReset), "NET_RESET"),
+            (Error::from(ErrorKind::ConnectionAborted), "NET_RESET"),
+            (
+                Error::new(ErrorKind::TimedOut, "connect timed out"),
+                "NET_TIMEOUT",
+            ),
+            (Error::from(ErrorKind::WouldBlock), "NET_TIMEOUT"),
+            (Error::from(ErrorKind::HostUnreachable), "NET_UNREACHABLE"),
+            (
+                Error::from(ErrorKind::NetworkUnreachable),
+                "NET_UNREACHABLE",
+            ),
+            (Error::from(ErrorKind::UnexpectedEof), "NET"),
+            (Error::other("oops"), "NET"),
+        ] {
+            let error = MonitorError::Network(error);
+            assert_eq!(error.code(), code, "{}", error);
+            // Connection failures are still collapsed together by kind
+            assert_eq!(error.kind(), "network");
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use crate::{has_critical, Status};
+use aws_sdk_lambda::primitives::Blob;
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use lambda_runtime::tracing::{info, warn};
+use lambda_runtime::Error;
+use serde_json::json;
+use ssl_cert_monitor_common::Response;
+use std::time::Duration;
+use tokio::time::{timeout_at, Instant};
+
+/// Domains split between this invocation and invocations of the same function
+#[derive(Debug, Default, PartialEq)]
+pub struct FanOut {
+    /// Checked by this invocation
+    pub local: Vec<String>,
+    /// Chunks dispatched to other invocations, in order
+    pub dispatched: Vec<Vec<String>>,
+}
+
+/// Split domains into chunks of at most `max_domains`, keeping the first
+/// chunk for this invocation. Lists within the limit are checked locally.
+pub fn plan(domains: Vec<String>, max_domains: usize) -> FanOut {
+    if max_domains == 0 || domains.len() <= max_domains {
+        return FanOut {
+            local: domains,
+            dispatched: Vec::new(),
+        };
+    }
+    let mut chunks = domains.chunks(max_domains).map(<[String]>::to_vec);
+    FanOut {
+        local: chunks.next().unwrap_or_default(),
+        dispatched: chunks.collect(),
+    }
+}
+
+/// Status of a domain of a chunk whose invocation failed
+fn not_dispatched(domain: String, reason: &str) -> Status {
+    Status {
+        domain,
+        valid: false,
+        error: format!("not checked: {}", reason),
+        error_kind: Some("fan_out".into()),
+        error_code: Some("FAN_OUT".into()),
+        ..Default::default()
+    }
+}
+
+/// Invocation of the function itself with a chunk of domains
+pub trait Invoker {
+    async fn invoke(
+        &self,
+        function: &str,
+        domains: &[String],
+        as_of: Option<DateTime<Utc>>,
+        fail_fast: bool,
+    ) -> Result<Vec<Status>, Error>;
+}
+
+impl Invoker for aws_sdk_lambda::Client {
+    async fn invoke(
+        &self,
+        function: &str,
+        domains: &[String],
+        as_of: Option<DateTime<Utc>>,
+        fail_fast: bool,
+    ) -> Result<Vec<Status>, Error> {
+        let payload = json!({ "domains": domains, "as_of": as_of, "fail_fast": fail_fast });
+        let output = self
+            .invoke()
+            .function_name(function)
+            .payload(Blob::new(serde_json::to_vec(&payload)?))
+            .send()
+            .await
+            .map_err(Box::new)?;
+        if let Some(error) = output.function_error() {
+            return Err(format!("invocation failed with {} error", error).into());
+        }
+        let payload = output.payload().ok_or("empty invocation response")?;
+        let response: Response = serde_json::from_slice(payload.as_ref())?;
+        Ok(response.statuses)
+    }
+}
+
+/// Check a chunk by an invocation, reporting its domains as not checked
+/// if the invocation fails or does not finish before `deadline`
+async fn check_chunk<I: Invoker>(
+    invoker: &I,
+    function: &str,
+    chunk: Vec<String>,
+    as_of: Option<DateTime<Utc>>,
+    fail_fast: bool,
+    deadline: Instant,
+) -> Vec<Status> {
+    let reason =
+        match timeout_at(deadline, invoker.invoke(function, &chunk, as_of, fail_fast)).await {
+            Ok(Ok(statuses)) => return statuses,
+            Ok(Err(err)) => err.to_string(),
+            Err(_) => "deadline".to_string(),
+        };
+    warn!(
+        "Cannot check a chunk of {} domains: {}",
+        chunk.len(),
+        reason
+    );
+    chunk
+        .into_iter()
+        .map(|domain| not_dispatched(domain, &reason))
+        .collect()
+}
+
+/// Dispatch chunks to invocations of `function`, a qualified ARN of this
+/// function, and collect statuses in the original order within `timeout`.
+/// Domains of failed or unfinished chunks are reported as not checked,
+/// so results stay partial.
+/// Chunks are checked concurrently, except with `fail_fast`: running
+/// invocations cannot be cancelled, so chunks are then checked one by one
+/// and the rest is skipped after a chunk with a critical failure.
+pub async fn dispatch<I: Invoker>(
+    invoker: &I,
+    function: String,
+    chunks: Vec<Vec<String>>,
+    as_of: Option<DateTime<Utc>>,
+    fail_fast: bool,
+    timeout: Duration,
+) -> Vec<Status> {
+    if chunks.is_empty() {
+        return Vec::new();
+    }
+    info!("Dispatching {} chunks to {}", chunks.len(), function);
+    let deadline = Instant::now() + timeout;
+    if !fail_fast {
+        let checks = chunks
+            .into_iter()
+            .map(|chunk| check_chunk(invoker, &function, chunk, as_of, fail_fast, deadline));
+        return join_all(checks).await.into_iter().flatten().collect();
+    }
+    let mut statuses = Vec::new();
+    for chunk in chunks {
+        let total = chunk.len();
+        let chunk_statuses =
+            check_chunk(invoker, &function, chunk, as_of, fail_fast, deadline).await;
+        let stopped = chunk_statuses.len() < total || has_critical(&chunk_statuses);
+        statuses.extend(chunk_statuses);
+        if stopped {
+            warn!("Skipping chunks after a failed domain");
+            break;
+        }
+    }
+    statuses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn domains(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("d{}.com", i)).collect()
+    }
+
+    #[test]
+    fn test_plan() {
+        assert_eq!(
+            plan(domains(3), 3),
+            FanOut {
+                local: domains(3),
+                dispatched: Vec::new(),
+            }
+        );
+        assert_eq!(plan(domains(7), 0).local, domains(7));
+        assert_eq!(plan(Vec::new(), 2), FanOut::default());
+
+        let fan_out = plan(domains(7), 3);
+        assert_eq!(fan_out.local, vec!["d0.com", "d1.com", "d2.com"]);
+        assert_eq!(
+            fan_out.dispatched,
+            vec![vec!["d3.com", "d4.com", "d5.com"], vec!["d6.com"],]
+        );
+
+        let fan_out = plan(domains(4), 1);
+        assert_eq!(fan_out.local, vec!["d0.com"]);
+        assert_eq!(fan_out.dispatched.len(), 3);
+    }
+
+    #[test]
+    fn test_not_dispatched() {
+        let status = not_dispatched("d0.com".into(), "throttled");
+        assert!(!status.valid);
+        assert_eq!(status.error, "not checked: throttled");
+        assert_eq!(status.error_code.as_deref(), Some("FAN_OUT"));
+    }
+
+    /// Invoker checking every domain except the ones of failing chunks
+    #[derive(Default)]
+    struct MockInvoker {
+        invoked: Mutex<Vec<(String, Vec<String>)>>,
+    }
+
+    impl Invoker for MockInvoker {
+        async fn invoke(
+            &self,
+            function: &str,
+            domains: &[String],
+            _as_of: Option<DateTime<Utc>>,
+            _fail_fast: bool,
+        ) -> Result<Vec<Status>, Error> {
+            self.invoked
+                .lock()
+                .unwrap()
+                .push((function.to_string(), domains.to_vec()));
+            if domains.iter().any(|domain| domain == "slow.com") {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+            }
+            if domains.iter().any(|domain| domain == "throttled.com") {
+                return Err("throttled".into());
+            }
+            Ok(domains
+                .iter()
+                .map(|domain| Status {
+                    domain: domain.clone(),
+                    valid: domain != "invalid.com",
+                    ..Default::default()
+                })
+                .collect())
+        }
+    }
+
+    fn chunks(chunks: &[&[&str]]) -> Vec<Vec<String>> {
+        chunks
+            .iter()
+            .map(|chunk| chunk.iter().map(|domain| domain.to_string()).collect())
+            .collect()
+    }
+
+    const FUNCTION: &str = "arn:aws:lambda:eu-west-1:123456789012:function:monitor:prod";
+
+    #[tokio::test]
+    async fn test_dispatch() {
+        let invoker = MockInvoker::default();
+        let statuses = dispatch(
+            &invoker,
+            FUNCTION.into(),
+            chunks(&[&["a.com", "b.com"], &["throttled.com", "c.com"], &["d.com"]]),
+            None,
+            false,
+            Duration::from_secs(10),
+        )
+        .await;
+        let domains: Vec<_> = statuses
+            .iter()
+            .map(|status| status.domain.as_str())
+            .collect();
+        assert_eq!(
+            domains,
+            ["a.com", "b.com", "throttled.com", "c.com", "d.com"]
+        );
+        let failed: Vec<_> = statuses
+            .iter()
+            .filter(|status| !status.valid)
+            .map(|status| (status.domain.as_str(), status.error.as_str()))
+            .collect();
+        assert_eq!(
+            failed,
+            [
+                ("throttled.com", "not checked: throttled"),
+                ("c.com", "not checked: throttled")
+            ]
+        );
+        let invoked = invoker.invoked.lock().unwrap();
+        assert_eq!(invoked.len(), 3);
+        assert!(invoked.iter().all(|(function, _)| function == FUNCTION));
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_deadline() {
+        let invoker = MockInvoker::default();
+        let statuses = dispatch(
+            &invoker,
+            FUNCTION.into(),
+            chunks(&[&["a.com"], &["slow.com", "b.com"]]),
+            None,
+            false,
+            Duration::from_millis(100),
+        )
+        .await;
+        assert!(statuses[0].valid);
+        assert_eq!(statuses[1].error, "not checked: deadline");
+        assert_eq!(statuses[2].error_kind.as_deref(), Some("fan_out"));
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_fail_fast() {
+        let invoker = MockInvoker::default();
+        let statuses = dispatch(
+            &invoker,
+            FUNCTION.into(),
+            chunks(&[&["a.com"], &["invalid.com", "b.com"], &["c.com"]]),
+            None,
+            true,
+            Duration::from_secs(10),
+        )
+        .await;
+        assert_eq!(statuses.len(), 3);
+        assert!(!statuses[1].valid);
+        // The chunk after the invalid domain is never invoked
+        assert_eq!(invoker.invoked.lock().unwrap().len(), 2);
+    }
+}
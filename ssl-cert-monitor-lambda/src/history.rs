@@ -0,0 +1,298 @@
+use crate::Status;
+use aws_sdk_dynamodb::operation::put_item::PutItemError;
+use aws_sdk_dynamodb::types::AttributeValue;
+use chrono::{DateTime, SecondsFormat, Utc};
+use lambda_runtime::tracing::{info, warn};
+use lambda_runtime::Error;
+use std::collections::HashMap;
+
+pub type Item = HashMap<String, AttributeValue>;
+
+/// Sort key of request claims, kept in the history table next to results
+const CLAIM_SORT_KEY: &str = "claim";
+
+/// How long request claims are kept, for a TTL on the `expires_at` attribute
+const CLAIM_TTL: chrono::Duration = chrono::Duration::days(1);
+
+/// Storage for historical check results
+pub trait HistoryStore {
+    async fn put(&self, table: &str, item: Item) -> Result<(), Error>;
+
+    /// Record `checked_at` as the check time of request `req_id` unless
+    /// an earlier attempt did, and return the recorded check time
+    async fn claim(
+        &self,
+        table: &str,
+        req_id: &str,
+        checked_at: DateTime<Utc>,
+    ) -> Result<DateTime<Utc>, Error>;
+}
+
+impl HistoryStore for aws_sdk_dynamodb::Client {
+    async fn put(&self, table: &str, item: Item) -> Result<(), Error> {
+        self.put_item()
+            .table_name(table)
+            .set_item(Some(item))
+            .send()
+            .await
+            .map_err(Box::new)?;
+        Ok(())
+    }
+
+    async fn claim(
+        &self,
+        table: &str,
+        req_id: &str,
+        checked_at: DateTime<Utc>,
+    ) -> Result<DateTime<Utc>, Error> {
+        let res = self
+            .put_item()
+            .table_name(table)
+            .item("domain", AttributeValue::S(claim_key(req_id)))
+            .item("checked_at", AttributeValue::S(CLAIM_SORT_KEY.into()))
+            .item("first_checked_at", AttributeValue::S(timestamp(checked_at)))
+            .item(
+                "expires_at",
+                AttributeValue::N((checked_at + CLAIM_TTL).timestamp().to_string()),
+            )
+            // `domain` is a reserved word in expressions
+            .condition_expression("attribute_not_exists(#domain)")
+            .expression_attribute_names("#domain", "domain")
+            .send()
+            .await;
+        match res {
+            Ok(_) => Ok(checked_at),
+            Err(err)
+                if matches!(
+                    err.as_service_error(),
+                    Some(PutItemError::ConditionalCheckFailedException(_))
+                ) =>
+            {
+                let output = self
+                    .get_item()
+                    .table_name(table)
+                    .key("domain", AttributeValue::S(claim_key(req_id)))
+                    .key("checked_at", AttributeValue::S(CLAIM_SORT_KEY.into()))
+                    .consistent_read(true)
+                    .send()
+                    .await
+                    .map_err(Box::new)?;
+                let first_checked_at = output
+                    .item
+                    .as_ref()
+                    .and_then(|item| item.get("first_checked_at")?.as_s().ok())
+                    .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+                    .map(|value| value.to_utc());
+                Ok(first_checked_at.unwrap_or(checked_at))
+            }
+            Err(err) => Err(Box::new(err).into()),
+        }
+    }
+}
+
+/// Partition key of the claim of a request
+fn claim_key(req_id: &str) -> String {
+    format!("request#{}", req_id)
+}
+
+fn timestamp(value: DateTime<Utc>) -> String {
+    value.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Convert a domain status to a history item keyed by domain and check time
+pub fn history_item(status: &Status, checked_at: DateTime<Utc>, req_id: &str) -> Item {
+    let mut item = HashMap::from([
+        (
+            "domain".to_string(),
+            AttributeValue::S(status.domain.clone()),
+        ),
+        (
+            "checked_at".to_string(),
+            AttributeValue::S(timestamp(checked_at)),
+        ),
+        ("valid".to_string(), AttributeValue::Bool(status.valid)),
+        ("req_id".to_string(), AttributeValue::S(req_id.to_string())),
+    ]);
+    if let Some(days_remaining) = status.days_remaining {
+        item.insert(
+            "days_remaining".to_string(),
+            AttributeValue::N(days_remaining.to_string()),
+        );
+    }
+    if let Some(not_after) = status.not_after {
+        item.insert(
+            "not_after".to_string(),
+            AttributeValue::S(timestamp(not_after)),
+        );
+    }
+    item
+}
+
+/// Write one history item per domain. Items of a retried invocation use
+/// the check time of the first attempt of request `req_id`, so they overwrite
+/// items written before instead of duplicating them.
+pub async fn write_history<S: HistoryStore>(
+    store: &S,
+    table: &str,
+    statuses: &[Status],
+    checked_at: DateTime<Utc>,
+    req_id: &str,
+) -> Result<(), Error> {
+    let claimed_at = store.claim(table, req_id, checked_at).await?;
+    if claimed_at != checked_at {
+        warn!(
+            "Request {} is retried, overwriting history checked at {}",
+            req_id, claimed_at
+        );
+    }
+    for status in statuses {
+        store
+            .put(table, history_item(status, claimed_at, req_id))
+            .await?;
+    }
+    info!("Wrote {} history items to {}", statuses.len(), table);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn checked_at() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2024-05-01T10:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_history_item() {
+        let status = Status {
+            domain: "example.com".into(),
+            valid: true,
+            days_remaining: Some(42),
+            not_after: Some(
+                DateTime::parse_from_rfc3339("2024-06-12T12:00:00Z")
+                    .unwrap()
+                    .with_timezone(&Utc),
+            ),
+            ..Default::default()
+        };
+        let item = history_item(&status, checked_at(), "42");
+        assert_eq!(item.len(), 6);
+        assert_eq!(item["domain"], AttributeValue::S("example.com".into()));
+        assert_eq!(
+            item["checked_at"],
+            AttributeValue::S("2024-05-01T10:00:00Z".into())
+        );
+        assert_eq!(item["valid"], AttributeValue::Bool(true));
+        assert_eq!(item["req_id"], AttributeValue::S("42".into()));
+        assert_eq!(item["days_remaining"], AttributeValue::N("42".into()));
+        assert_eq!(
+            item["not_after"],
+            AttributeValue::S("2024-06-12T12:00:00Z".into())
+        );
+    }
+
+    #[test]
+    fn test_history_item_failed() {
+        let status = Status {
+            domain: "example.com".into(),
+            valid: false,
+            error: "network error".into(),
+            ..Default::default()
+        };
+        let item = history_item(&status, checked_at(), "42");
+        assert_eq!(item.len(), 4);
+        assert_eq!(item["valid"], AttributeValue::Bool(false));
+    }
+
+    #[derive(Default)]
+    struct MockStore {
+        items: Mutex<Vec<(String, Item)>>,
+        claims: Mutex<HashMap<String, DateTime<Utc>>>,
+    }
+
+    impl MockStore {
+        /// Distinct items by their primary key, as stored by DynamoDB
+        fn records(&self) -> usize {
+            let items = self.items.lock().unwrap();
+            let keys: std::collections::HashSet<_> = items
+                .iter()
+                .map(|(_, item)| (item["domain"].clone(), item["checked_at"].clone()))
+                .map(|(domain, checked_at)| format!("{:?} {:?}", domain, checked_at))
+                .collect();
+            keys.len()
+        }
+    }
+
+    impl HistoryStore for MockStore {
+        async fn put(&self, table: &str, item: Item) -> Result<(), Error> {
+            self.items.lock().unwrap().push((table.to_string(), item));
+            Ok(())
+        }
+
+        async fn claim(
+            &self,
+            _table: &str,
+            req_id: &str,
+            checked_at: DateTime<Utc>,
+        ) -> Result<DateTime<Utc>, Error> {
+            Ok(*self
+                .claims
+                .lock()
+                .unwrap()
+                .entry(req_id.to_string())
+                .or_insert(checked_at))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_history() {
+        let store = MockStore::default();
+        let statuses = vec![
+            Status {
+                domain: "a.com".into(),
+                ..Default::default()
+            },
+            Status {
+                domain: "b.com".into(),
+                ..Default::default()
+            },
+        ];
+        write_history(&store, "history", &statuses, checked_at(), "42")
+            .await
+            .expect("should succeed");
+        let items = store.items.lock().unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].0, "history");
+        assert_eq!(items[1].1["domain"], AttributeValue::S("b.com".into()));
+    }
+
+    #[tokio::test]
+    async fn test_write_history_retried() {
+        let store = MockStore::default();
+        let statuses = vec![Status {
+            domain: "a.com".into(),
+            ..Default::default()
+        }];
+        let retried_at = checked_at() + chrono::Duration::minutes(5);
+        write_history(&store, "history", &statuses, checked_at(), "42")
+            .await
+            .unwrap();
+        write_history(&store, "history", &statuses, retried_at, "42")
+            .await
+            .unwrap();
+        assert_eq!(store.records(), 1);
+        assert_eq!(
+            store.items.lock().unwrap()[1].1["checked_at"],
+            AttributeValue::S("2024-05-01T10:00:00Z".into())
+        );
+
+        // Another request is a separate record
+        write_history(&store, "history", &statuses, retried_at, "43")
+            .await
+            .unwrap();
+        assert_eq!(store.records(), 2);
+    }
+}
@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// HTTP request from a Function URL or an API Gateway (payload format 1.0 and 2.0)
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpRequest {
+    /// Method of API Gateway REST APIs
+    #[serde(default)]
+    http_method: Option<String>,
+    request_context: RequestContext,
+    #[serde(default)]
+    query_string_parameters: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RequestContext {
+    /// Method and path of Function URLs and HTTP APIs
+    #[serde(default)]
+    http: Option<HttpContext>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HttpContext {
+    method: String,
+}
+
+impl HttpRequest {
+    pub fn method(&self) -> &str {
+        self.request_context
+            .http
+            .as_ref()
+            .map(|http| http.method.as_str())
+            .or(self.http_method.as_deref())
+            .unwrap_or("GET")
+    }
+
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.query_string_parameters
+            .as_ref()?
+            .get(name)
+            .map(String::as_str)
+    }
+}
+
+/// HTTP response understood by Function URLs and API Gateway
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpResponse {
+    pub status_code: u16,
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+impl HttpResponse {
+    fn new(status_code: u16, content_type: &str, body: String) -> Self {
+        Self {
+            status_code,
+            headers: HashMap::from([("content-type".to_string(), content_type.to_string())]),
+            body,
+        }
+    }
+
+    pub fn json(body: String) -> Self {
+        Self::new(200, "application/json", body)
+    }
+
+    pub fn html(body: String) -> Self {
+        Self::new(200, "text/html; charset=utf-8", body)
+    }
+
+    pub fn prometheus(body: String) -> Self {
+        Self::new(200, "text/plain; version=0.0.4", body)
+    }
+
+    pub fn error(status_code: u16, message: &str) -> Self {
+        Self::new(
+            status_code,
+            "text/plain; charset=utf-8",
+            message.to_string(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_function_url_request() {
+        let request: HttpRequest = serde_json::from_str(
+            r#"{
+                "version": "2.0",
+                "rawPath": "/",
+                "queryStringParameters": {"format": "html"},
+                "requestContext": {"http": {"method": "GET", "path": "/"}}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(request.method(), "GET");
+        assert_eq!(request.query("format"), Some("html"));
+        assert_eq!(request.query("missing"), None);
+    }
+
+    #[test]
+    fn test_rest_api_request() {
+        let request: HttpRequest = serde_json::from_str(
+            r#"{
+                "httpMethod": "POST",
+                "path": "/",
+                "queryStringParameters": null,
+                "requestContext": {"stage": "prod"}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(request.method(), "POST");
+        assert_eq!(request.query("format"), None);
+    }
+
+    #[test]
+    fn test_response() {
+        let response =
+            serde_json::to_value(HttpResponse::error(405, "Method not allowed")).unwrap();
+        assert_eq!(response["statusCode"], 405);
+        assert_eq!(
+            response["headers"]["content-type"],
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(response["body"], "Method not allowed");
+    }
+}
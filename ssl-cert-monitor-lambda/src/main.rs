@@ -1,56 +1,469 @@
+mod breaker;
 mod cert;
+mod cli;
+mod config;
+mod dane;
+mod dynamodb;
 mod error;
+mod fanout;
+mod history;
+mod http;
+mod output;
+mod quic;
+mod ratelimit;
+mod resolver;
+mod rotation;
+mod schedule;
+mod secrets;
+mod sink;
+mod verifier;
+mod xray;
 
-use lambda_runtime::{run, service_fn, tracing, Error, LambdaEvent};
+use lambda_runtime::{run, service_fn, tracing, Context, Error, LambdaEvent};
 
-use crate::cert::Validator;
+use crate::cert::{CertInfo, DomainEntry, Validator};
 use crate::error::MonitorError;
 use aws_config::meta::region::RegionProviderChain;
+use aws_sdk_s3::config::http::HttpResponse;
+use aws_sdk_s3::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_s3::operation::get_object::GetObjectError;
+use aws_sdk_s3::types::ServerSideEncryption;
 use aws_sdk_s3::Client;
-use chrono::Utc;
-use lambda_runtime::tracing::info;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+use lambda_runtime::tracing::{info, warn};
+use rand::seq::SliceRandom;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use ssl_cert_monitor_common::{common_failure, parse_warning_kinds, Response, Severity, Status};
+use std::collections::HashSet;
 use std::str;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
 use url::Url;
 
 /// Requests come into the runtime as unicode
 /// strings in json format, which can map to any structure that implements `serde::Deserialize`
 /// The runtime pays no attention to the contents of the request payload.
-#[derive(Deserialize)]
+#[derive(Deserialize, Default)]
 struct Request {
+    /// Not needed when a certificate is passed inline
+    #[serde(default)]
     s3_config_location: String,
+    /// Validate certificates as of this moment instead of now, for back-testing
+    #[serde(default)]
+    as_of: Option<DateTime<Utc>>,
+    /// Inline PEM certificate chain to validate instead of the configured domains
+    #[serde(default)]
+    cert_pem: Option<String>,
+    /// Inline base64-encoded DER certificate to validate instead of the configured domains
+    #[serde(default)]
+    cert_der_base64: Option<String>,
+    /// Stop at the first invalid domain, also enabled with `FAIL_FAST`
+    #[serde(default)]
+    fail_fast: bool,
+    /// Environment substituted for `{env}` in the config location
+    #[serde(default)]
+    environment: Option<String>,
+    /// Chunk of domains dispatched by another invocation, checked instead of the config
+    #[serde(default)]
+    domains: Option<Vec<String>>,
 }
 
-#[derive(Serialize)]
-struct Status {
-    domain: String,
-    valid: bool,
-    error: String,
+/// Placeholder of the environment in config locations
+const ENVIRONMENT_PLACEHOLDER: &str = "{env}";
+
+/// Substitute the environment into a config location template like
+/// `s3://bucket/domains-{env}.txt`. Locations without the placeholder are kept as is.
+fn config_location(template: &str, environment: Option<&str>) -> Result<String, MonitorError> {
+    if !template.contains(ENVIRONMENT_PLACEHOLDER) {
+        return Ok(template.to_string());
+    }
+    let environment = environment.ok_or_else(|| {
+        MonitorError::Config(format!("No environment for config location {}", template))
+    })?;
+    // Keep the environment within a key name
+    let valid = !environment.is_empty()
+        && environment
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(MonitorError::Config(format!(
+            "Wrong environment {}",
+            environment
+        )));
+    }
+    Ok(template.replace(ENVIRONMENT_PLACEHOLDER, environment))
+}
+
+/// Split config locations separated by commas or whitespace
+fn split_locations(value: &str) -> Vec<&str> {
+    value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|location| !location.is_empty())
+        .collect()
+}
+
+/// Merge domains of several configs in order, dropping duplicates
+fn merge_domains(sources: Vec<Vec<String>>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    sources
+        .into_iter()
+        .flatten()
+        .filter(|domain| seen.insert(domain.clone()))
+        .collect()
+}
+
+/// Expand config lines listing several ports into a line per port. Malformed
+/// lines are kept as is to be reported as failed config statuses.
+fn expand_domains(domains: Vec<String>) -> Vec<String> {
+    domains
+        .into_iter()
+        .flat_map(|domain| match cert::expand_ports(&domain) {
+            Ok(expanded) => expanded,
+            Err(err) => {
+                warn!("Cannot expand ports of {}: {}", domain, err);
+                vec![domain]
+            }
+        })
+        .collect()
+}
+
+/// Failed status for a config without any domain to check, so a config that
+/// went missing or was emptied does not look like a successful run
+fn empty_config(domains: &[String], config_errors: &[Status], location: &str) -> Option<Status> {
+    if !domains.is_empty() || !config_errors.is_empty() {
+        return None;
+    }
+    Some(domain_status(
+        location.to_string(),
+        Err(MonitorError::Config("no domains to check".into())),
+    ))
 }
 
-/// The runtime requires responses to be serialized into json.
-/// The runtime pays no attention to the contents of the response payload.
+/// Failed `config` status of a config location that cannot be read while
+/// other locations can
+fn unreadable_config(location: &str, err: &Error) -> Status {
+    domain_status(
+        location.to_string(),
+        Err(MonitorError::Config(format!("cannot read config: {}", err))),
+    )
+}
+
+/// Lambda event, either a direct invocation, an HTTP request from a Function URL
+/// or a keep-alive ping
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Event {
+    Http(http::HttpRequest),
+    Warmup(Warmup),
+    Direct(Request),
+}
+
+/// Keep-alive ping of a scheduled warmer answered without any checks
+#[derive(Deserialize)]
+#[serde(try_from = "WarmupShape")]
+struct Warmup;
+
+/// Fields telling warmup pings apart from check requests
+#[derive(Deserialize)]
+struct WarmupShape {
+    #[serde(default)]
+    warmup: bool,
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default, rename = "detail-type")]
+    detail_type: Option<String>,
+}
+
+impl TryFrom<WarmupShape> for Warmup {
+    type Error = &'static str;
+
+    /// Accept `{"warmup": true}`, pings of serverless-plugin-warmup and bare
+    /// EventBridge scheduled events, which carry no config location anyway
+    fn try_from(shape: WarmupShape) -> Result<Self, Self::Error> {
+        let scheduled = shape.source.as_deref() == Some("aws.events")
+            && shape.detail_type.as_deref() == Some("Scheduled Event");
+        let plugin = shape.source.as_deref() == Some("serverless-plugin-warmup");
+        if shape.warmup || scheduled || plugin {
+            Ok(Warmup)
+        } else {
+            Err("not a warmup event")
+        }
+    }
+}
+
+/// Response matching the shape of the event
 #[derive(Serialize)]
-struct Response {
-    req_id: String,
-    statuses: Vec<Status>,
+#[serde(untagged)]
+enum Output {
+    Http(http::HttpResponse),
+    Direct(Response),
 }
 
-async fn parse_domains(s3_config_location: &str) -> Result<Vec<String>, Error> {
+/// Domain reported for a certificate passed inline
+const INLINE_DOMAIN: &str = "inline";
+
+impl Request {
+    /// Decoded inline certificate, if any
+    fn inline_certificate(&self) -> Option<Result<Vec<u8>, MonitorError>> {
+        if let Some(pem) = &self.cert_pem {
+            return Some(Ok(pem.clone().into_bytes()));
+        }
+        let encoded = self.cert_der_base64.as_ref()?;
+        let encoded: String = encoded.split_whitespace().collect();
+        Some(
+            base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .map_err(|err| {
+                    MonitorError::Config(format!("Invalid base64 certificate: {}", err))
+                }),
+        )
+    }
+}
+
+/// Default number of domains validated concurrently
+const DEFAULT_CONCURRENCY: usize = 25;
+
+/// Upper limit of concurrency to keep file descriptors and rate limits in check
+const MAX_CONCURRENCY: usize = 100;
+
+/// Upper bounds (exclusive) of days-remaining histogram buckets
+const BUCKET_BOUNDS: [i64; 3] = [7, 30, 90];
+
+/// Count domains by days remaining until certificate expiration.
+/// Domains without a known expiration date are not counted.
+fn histogram(statuses: &[Status]) -> Vec<(String, usize)> {
+    let mut counts = [0usize; BUCKET_BOUNDS.len() + 1];
+    for days in statuses.iter().filter_map(|status| status.days_remaining) {
+        let index = BUCKET_BOUNDS
+            .iter()
+            .position(|&bound| days < bound)
+            .unwrap_or(BUCKET_BOUNDS.len());
+        counts[index] += 1;
+    }
+
+    let mut labels = vec![format!("<{}d", BUCKET_BOUNDS[0])];
+    labels.extend(
+        BUCKET_BOUNDS
+            .windows(2)
+            .map(|pair| format!("{}-{}d", pair[0], pair[1])),
+    );
+    labels.push(format!(">{}d", BUCKET_BOUNDS[BUCKET_BOUNDS.len() - 1]));
+
+    labels.into_iter().zip(counts).collect()
+}
+
+/// Interval until the next check, shorter as the earliest expiration approaches
+fn recheck_interval(min_days_remaining: Option<i64>) -> chrono::Duration {
+    match min_days_remaining {
+        Some(days) if days < 7 => chrono::Duration::hours(1),
+        Some(days) if days <= 30 => chrono::Duration::hours(6),
+        _ => chrono::Duration::days(1),
+    }
+}
+
+/// Recommended time of the next check based on the earliest expiration
+fn recommended_recheck_at(statuses: &[Status], now: DateTime<Utc>) -> DateTime<Utc> {
+    let min_days_remaining = statuses
+        .iter()
+        .filter_map(|status| status.days_remaining)
+        .min();
+    now + recheck_interval(min_days_remaining)
+}
+
+/// Convert S3 access errors into a config error, since they usually mean
+/// missing permissions on the bucket or on the KMS key used to encrypt it
+fn map_get_object_error(
+    err: SdkError<GetObjectError, HttpResponse>,
+    s3_config_location: &str,
+    sse_kms_key_id: Option<&str>,
+) -> Error {
+    match err.code() {
+        Some("AccessDenied") | Some("KMS.AccessDeniedException") => MonitorError::Config(format!(
+            "Access denied to S3 config {} (KMS key {})",
+            s3_config_location,
+            sse_kms_key_id.unwrap_or("not specified")
+        ))
+        .into(),
+        Some("NoSuchKey") => {
+            MonitorError::Config(format!("S3 config {} does not exist", s3_config_location)).into()
+        }
+        _ => Box::new(err).into(),
+    }
+}
+
+/// Attempts to fetch the config from S3, retried by the SDK when throttled
+const S3_FETCH_ATTEMPTS: u32 = 4;
+
+/// Base delay of the exponential backoff between retried S3 fetches
+const S3_BACKOFF_BASE: Duration = Duration::from_millis(200);
+
+/// Retries of S3 fetches: the SDK retries throttling (`SlowDown`, HTTP 503)
+/// and transient errors with a jittered exponential backoff
+fn s3_retry_config() -> aws_sdk_s3::config::retry::RetryConfig {
+    aws_sdk_s3::config::retry::RetryConfig::standard()
+        .with_max_attempts(S3_FETCH_ATTEMPTS)
+        .with_initial_backoff(S3_BACKOFF_BASE)
+}
+
+/// Ensure the config object is encrypted with the expected KMS key if one is specified.
+/// GetObject decrypts SSE-KMS objects transparently, so the key cannot be passed
+/// to the request itself and is verified against the response instead.
+fn check_encryption(
+    sse_kms_key_id: Option<&str>,
+    encryption: Option<&ServerSideEncryption>,
+    object_key_id: Option<&str>,
+) -> Result<(), MonitorError> {
+    let Some(expected_key_id) = sse_kms_key_id else {
+        return Ok(());
+    };
+    let is_kms = matches!(
+        encryption,
+        Some(ServerSideEncryption::AwsKms) | Some(ServerSideEncryption::AwsKmsDsse)
+    );
+    // Object reports a full key ARN, while the configured one may be a bare key id
+    let matches = |key_id: &str| {
+        key_id == expected_key_id
+            || key_id
+                .rsplit_once(":key/")
+                .is_some_and(|(_, id)| id == expected_key_id)
+    };
+    match object_key_id {
+        Some(key_id) if is_kms && matches(key_id) => Ok(()),
+        _ => Err(MonitorError::Config(format!(
+            "S3 config is not encrypted with KMS key {} (found {:?} with key {:?})",
+            expected_key_id, encryption, object_key_id
+        ))),
+    }
+}
+
+async fn load_aws_config() -> aws_config::SdkConfig {
     let region_provider = RegionProviderChain::default_provider().or_else("us-east-1");
-    let config = aws_config::defaults(aws_config::BehaviorVersion::latest())
+    aws_config::defaults(aws_config::BehaviorVersion::latest())
         .region(region_provider)
         .load()
-        .await;
-    let client = Client::new(&config);
+        .await
+}
+
+/// Extract bucket and key from `s3://bucket/key`, virtual-hosted style
+/// `https://bucket.s3.region.amazonaws.com/key` or path style
+/// `https://s3.region.amazonaws.com/bucket/key` location
+fn parse_s3_location(location: &str) -> Result<(String, String), MonitorError> {
+    let wrong_location = || MonitorError::Config("Cannot parse S3 url ".to_owned() + location);
+    let url = Url::parse(location).map_err(|_| wrong_location())?;
+    let host = url.host_str().ok_or_else(wrong_location)?;
+    let path = percent_encoding::percent_decode_str(url.path().trim_start_matches('/'))
+        .decode_utf8()
+        .map_err(|_| wrong_location())?;
+
+    let (bucket, key) = match url.scheme() {
+        "s3" => (host.to_string(), path.into_owned()),
+        "https" | "http" => {
+            let labels: Vec<&str> = host.split('.').collect();
+            let s3_label = labels
+                .iter()
+                .position(|label| *label == "s3" || label.starts_with("s3-"))
+                .ok_or_else(wrong_location)?;
+            if s3_label == 0 {
+                // Path style, bucket is the first path segment
+                let (bucket, key) = path.split_once('/').ok_or_else(wrong_location)?;
+                (bucket.to_string(), key.to_string())
+            } else {
+                (labels[..s3_label].join("."), path.into_owned())
+            }
+        }
+        _ => return Err(wrong_location()),
+    };
+    if bucket.is_empty() || key.is_empty() {
+        return Err(wrong_location());
+    }
+    Ok((bucket, key))
+}
+
+/// Where a config is read from
+#[derive(Debug, PartialEq)]
+enum ConfigSource<'a> {
+    S3(&'a str),
+    /// SSM parameter name, like `domains` or `/prod/domains`
+    Ssm(String),
+}
 
-    let url = Url::parse(s3_config_location).or(Err(MonitorError::Config(
-        "Cannot parse S3 url ".to_owned() + s3_config_location,
-    )))?;
-    let bucket = url.domain().ok_or(MonitorError::Config(
-        "Cannot parse S3 url ".to_owned() + s3_config_location,
-    ))?;
-    let object = url.path().trim_start_matches('/');
+/// Select the config source by the location scheme, S3 by default
+fn config_source(location: &str) -> Result<ConfigSource<'_>, MonitorError> {
+    let Some(name) = location.strip_prefix("ssm://") else {
+        return Ok(ConfigSource::S3(location));
+    };
+    let name = name.trim_end_matches('/');
+    if name.trim_start_matches('/').is_empty() {
+        return Err(MonitorError::Config(format!(
+            "Missing SSM parameter name in {}",
+            location
+        )));
+    }
+    // Hierarchical names are absolute, so `ssm://prod/domains` is `/prod/domains`
+    Ok(if name.contains('/') && !name.starts_with('/') {
+        ConfigSource::Ssm(format!("/{}", name))
+    } else {
+        ConfigSource::Ssm(name.to_string())
+    })
+}
+
+/// Read domains from a newline-delimited SSM parameter, decrypting secure strings
+async fn parse_ssm_domains(
+    config: &aws_config::SdkConfig,
+    name: &str,
+) -> Result<Vec<String>, Error> {
+    info!("Read config from SSM parameter {}", name);
+    let output = aws_sdk_ssm::Client::new(config)
+        .get_parameter()
+        .name(name)
+        .with_decryption(true)
+        .send()
+        .await
+        .map_err(|err| -> Error {
+            if err
+                .as_service_error()
+                .is_some_and(|err| err.is_parameter_not_found())
+            {
+                MonitorError::Config(format!("SSM parameter {} does not exist", name)).into()
+            } else {
+                Box::new(err).into()
+            }
+        })?;
+    let value = output
+        .parameter
+        .and_then(|parameter| parameter.value)
+        .unwrap_or_default();
+    read_lines(value.as_bytes()).await
+}
+
+/// Read domains from the config location, S3 or SSM
+async fn fetch_domains(
+    config: &aws_config::SdkConfig,
+    location: &str,
+    sse_kms_key_id: Option<&str>,
+) -> Result<Vec<String>, Error> {
+    match config_source(location)? {
+        ConfigSource::S3(location) => parse_domains(config, location, sse_kms_key_id).await,
+        ConfigSource::Ssm(name) => parse_ssm_domains(config, &name).await,
+    }
+}
+
+async fn parse_domains(
+    config: &aws_config::SdkConfig,
+    s3_config_location: &str,
+    sse_kms_key_id: Option<&str>,
+) -> Result<Vec<String>, Error> {
+    let client = Client::from_conf(
+        aws_sdk_s3::config::Builder::from(config)
+            .retry_config(s3_retry_config())
+            .build(),
+    );
+
+    let (bucket, object) = parse_s3_location(s3_config_location)?;
 
     info!(
         "Parse S3 config location {} to bucket: {}, url: {}",
@@ -59,21 +472,679 @@ async fn parse_domains(s3_config_location: &str) -> Result<Vec<String>, Error> {
 
     let object = client
         .get_object()
+        .bucket(&bucket)
+        .key(&object)
+        .send()
+        .await
+        .map_err(|err| map_get_object_error(err, s3_config_location, sse_kms_key_id))?;
+
+    check_encryption(
+        sse_kms_key_id,
+        object.server_side_encryption(),
+        object.ssekms_key_id(),
+    )?;
+
+    read_lines(object.body.into_async_read()).await
+}
+
+/// Upload results rendered as CSV to an S3 location
+async fn export_csv(client: &Client, location: &str, csv: String) -> Result<(), Error> {
+    let (bucket, key) = parse_s3_location(location)?;
+    client
+        .put_object()
         .bucket(bucket)
-        .key(object)
+        .key(key)
+        .content_type("text/csv; charset=utf-8")
+        .body(csv.into_bytes().into())
         .send()
         .await
         .map_err(Box::new)?;
+    Ok(())
+}
+
+/// Default prefix of timestamped JSON reports under the report location
+const DEFAULT_REPORT_ARCHIVE_PREFIX: &str = "archive/";
+
+/// Default key of the JSON report overwritten by every run
+const DEFAULT_REPORT_LATEST_KEY: &str = "latest.json";
+
+/// Keys of the timestamped archive copy and of the stable latest report under `prefix`
+fn report_keys(
+    prefix: &str,
+    archive_prefix: &str,
+    latest_key: &str,
+    checked_at: DateTime<Utc>,
+) -> (String, String) {
+    let prefix = match prefix.trim_end_matches('/') {
+        "" => String::new(),
+        prefix => format!("{}/", prefix),
+    };
+    (
+        format!(
+            "{}{}{}.json",
+            prefix,
+            archive_prefix,
+            checked_at.format("%Y-%m-%dT%H%M%SZ")
+        ),
+        format!("{}{}", prefix, latest_key),
+    )
+}
+
+/// Upload the JSON report to the archive first, then overwrite the latest one,
+/// so the latest report is always archived as well
+async fn export_report(
+    client: &Client,
+    location: &str,
+    checked_at: DateTime<Utc>,
+    json: String,
+) -> Result<(), Error> {
+    let (bucket, prefix) = parse_s3_location(location)?;
+    let archive_prefix = env_var::<String>("REPORT_ARCHIVE_PREFIX")
+        .unwrap_or_else(|| DEFAULT_REPORT_ARCHIVE_PREFIX.into());
+    let latest_key =
+        env_var::<String>("REPORT_LATEST_KEY").unwrap_or_else(|| DEFAULT_REPORT_LATEST_KEY.into());
+    let (archive, latest) = report_keys(&prefix, &archive_prefix, &latest_key, checked_at);
+    for key in [archive, latest] {
+        client
+            .put_object()
+            .bucket(&bucket)
+            .key(&key)
+            .content_type("application/json")
+            .body(json.clone().into_bytes().into())
+            .send()
+            .await
+            .map_err(Box::new)?;
+        info!("Uploaded report to s3://{}/{}", bucket, key);
+    }
+    Ok(())
+}
+
+/// Read non-empty lines incrementally without buffering the whole content
+async fn read_lines<R: AsyncBufRead + Unpin>(reader: R) -> Result<Vec<String>, Error> {
+    let mut lines = reader.lines();
+    let mut domains = Vec::new();
+    while let Some(line) = lines.next_line().await? {
+        if !line.is_empty() {
+            domains.push(line);
+        }
+    }
+    Ok(domains)
+}
+
+/// Domain line of the config, parsed once for checks and reports
+struct ConfigDomain {
+    line: String,
+    /// Endpoint of the line, `None` for bundles and malformed lines
+    entry: Option<DomainEntry>,
+}
+
+impl ConfigDomain {
+    fn new(line: String) -> Self {
+        let is_bundle = line.starts_with("file://") || secrets::is_secret(&line);
+        let entry = if is_bundle { None } else { line.parse().ok() };
+        ConfigDomain { line, entry }
+    }
+
+    /// Name in reports: the alias, or the config line itself
+    fn display_name(&self) -> String {
+        self.entry
+            .as_ref()
+            .and_then(|entry| entry.alias.clone())
+            .unwrap_or_else(|| self.line.clone())
+    }
+
+    /// Host connected to, the config line itself for bundles
+    fn host(&self) -> &str {
+        self.entry.as_ref().map_or(&self.line, |entry| &entry.host)
+    }
+
+    fn tier(&self) -> Option<String> {
+        self.entry.as_ref().and_then(|entry| entry.tier.clone())
+    }
+}
+
+fn check_domain(validator: &Validator, domain: &ConfigDomain) -> Status {
+    let started = std::time::Instant::now();
+    let res = tracing::info_span!("validate_domain", domain = %domain.line).in_scope(|| {
+        match &domain.entry {
+            Some(entry) => validator.validate_domain_entry(entry.clone()),
+            // Bundles, or malformed lines failing with a config error
+            None => validator.validate_domain(&domain.line),
+        }
+    });
+    let elapsed = started.elapsed();
+    if validator.is_slow(elapsed) {
+        warn!("Checking {} took {:?}", domain.line, elapsed);
+    }
+    // Report under the alias while connecting to the real target
+    Status {
+        tier: domain.tier(),
+        duration_ms: Some(elapsed.as_millis() as u64),
+        ..domain_status(domain.display_name(), res)
+    }
+}
+
+/// Status of a domain skipped after a recent healthy check
+fn cached_status(domain: String, last: &schedule::LastCheck, now: DateTime<Utc>) -> Status {
+    let domain = ConfigDomain::new(domain);
+    Status {
+        domain: domain.display_name(),
+        valid: true,
+        days_remaining: Some(last.days_remaining(now)),
+        not_after: Some(last.not_after),
+        tier: domain.tier(),
+        cached: true,
+        ..Default::default()
+    }
+}
+
+fn domain_status(domain: String, res: Result<CertInfo, MonitorError>) -> Status {
+    match res {
+        Ok(cert_info) => Status {
+            domain,
+            valid: true,
+            error: String::new(),
+            error_kind: None,
+            error_code: None,
+            days_remaining: Some(cert_info.days_remaining),
+            not_after: Some(cert_info.not_after),
+            alpn_protocol: cert_info.alpn_protocol,
+            ocsp_stapled: cert_info.ocsp_stapled,
+            expired_hours_ago: None,
+            organization: cert_info.organization,
+            issuer: cert_info.issuer,
+            distrusted_issuer: cert_info.distrusted_issuer,
+            validation_level: cert_info.validation_level,
+            sct_count: Some(cert_info.sct_count),
+            key_type: cert_info.key_type,
+            key_bits: cert_info.key_bits,
+            suppressed: false,
+            tier: None,
+            fingerprint: Some(cert_info.fingerprint),
+            rotated: false,
+            validity_days: Some(cert_info.validity_days),
+            validity_exceeded: cert_info.validity_exceeded,
+            validity_shortened: false,
+            cached: false,
+            spki_hash: cert_info.spki_hash,
+            late_alert: cert_info.late_alert,
+            uncovered_names: Vec::new(),
+            duration_ms: None,
+            checks_performed: cert_info.checks_performed,
+            redirects: cert_info.redirects,
+        },
+        Err(error) => Status {
+            domain,
+            valid: false,
+            error: error.to_string(),
+            error_kind: Some(error.kind().to_string()),
+            error_code: Some(error.code().to_string()),
+            days_remaining: match error {
+                MonitorError::Expired(days) => Some(days),
+                MonitorError::ExpiredAgo(hours) => Some(-hours / 24),
+                _ => None,
+            },
+            not_after: None,
+            alpn_protocol: None,
+            ocsp_stapled: false,
+            expired_hours_ago: match error {
+                MonitorError::ExpiredAgo(hours) => Some(hours),
+                _ => None,
+            },
+            organization: None,
+            issuer: None,
+            distrusted_issuer: None,
+            validation_level: None,
+            sct_count: None,
+            key_type: None,
+            key_bits: None,
+            suppressed: false,
+            tier: None,
+            fingerprint: None,
+            rotated: false,
+            validity_days: None,
+            validity_exceeded: false,
+            validity_shortened: false,
+            cached: false,
+            spki_hash: None,
+            late_alert: None,
+            uncovered_names: match error {
+                MonitorError::NamesNotCovered(names) => names,
+                _ => Vec::new(),
+            },
+            duration_ms: None,
+            checks_performed: Vec::new(),
+            redirects: Vec::new(),
+        },
+    }
+}
+
+/// Read and parse an environment variable, ignoring unset or malformed values
+fn env_var<T: str::FromStr>(name: &str) -> Option<T> {
+    std::env::var(name).ok()?.parse().ok()
+}
+
+/// Comma-separated names of an environment variable, empty if unset
+fn env_list(name: &str) -> Vec<String> {
+    parse_list(&env_var::<String>(name).unwrap_or_default())
+}
+
+/// Parse a comma-separated list, skipping empty names
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Create a validator configured from environment variables
+fn build_validator(now: DateTime<Utc>) -> Result<Validator, Error> {
+    let max_expiration: u64 = 10;
+
+    let mut validator = Validator::new(now, max_expiration)?;
+    if env_var::<bool>("NATIVE_ROOTS").unwrap_or(false) {
+        validator = validator.with_native_roots()?;
+    }
+    let read = |path: &str| {
+        std::fs::read(path)
+            .map_err(|err| MonitorError::Config(format!("Cannot read {}: {}", path, err)))
+    };
+    if let (Some(cert_path), Some(key_path)) = (
+        env_var::<String>("CLIENT_CERT_PATH"),
+        env_var::<String>("CLIENT_KEY_PATH"),
+    ) {
+        validator = validator.with_client_auth(&read(&cert_path)?, &read(&key_path)?)?;
+    }
+    let cipher_suites = env_list("CIPHER_SUITES");
+    let kx_groups = env_list("KX_GROUPS");
+    if !cipher_suites.is_empty() || !kx_groups.is_empty() {
+        validator =
+            validator.with_crypto_provider(cert::crypto_provider(&cipher_suites, &kx_groups)?)?;
+    }
+    if let Some(intermediates_path) = env_var::<String>("INTERMEDIATES_PATH") {
+        validator = validator.with_intermediates(&read(&intermediates_path)?)?;
+    }
+    if let Some(tier_thresholds) = env_var::<String>("TIER_THRESHOLDS") {
+        validator = validator.with_tier_thresholds(cert::parse_tier_thresholds(&tier_thresholds)?);
+    }
+    if let Some(clock_skew) = env_var::<i64>("CLOCK_SKEW_SECONDS") {
+        if clock_skew < 0 {
+            return Err(
+                MonitorError::Config("CLOCK_SKEW_SECONDS must not be negative".into()).into(),
+            );
+        }
+        validator = validator.with_clock_skew(chrono::Duration::seconds(clock_skew));
+    }
+    if let Some(retries) = env_var::<u32>("RETRIES") {
+        validator = validator.with_retries(retries);
+    }
+    if let Some(threshold) = env_var::<u32>("CIRCUIT_BREAKER_THRESHOLD") {
+        validator = validator.with_circuit_breaker(threshold);
+    }
+    if let Some(connections_per_second) =
+        env_var::<f64>("CONNECTIONS_PER_SECOND").filter(|rate| *rate > 0.0)
+    {
+        if connections_per_second < ratelimit::MIN_RATE {
+            return Err(MonitorError::Config(format!(
+                "CONNECTIONS_PER_SECOND must be at least {}",
+                ratelimit::MIN_RATE
+            ))
+            .into());
+        }
+        validator = validator.with_rate_limit(connections_per_second);
+    }
+    // A zero timeout would fail every connection right away
+    if let Some(timeout) = env_var::<u64>("TIMEOUT_SECONDS").filter(|timeout| *timeout > 0) {
+        validator = validator.with_timeout(std::time::Duration::from_secs(timeout));
+    }
+    if let Some(bind_address) = env_var::<std::net::IpAddr>("BIND_ADDRESS") {
+        validator = validator.with_bind_address(bind_address);
+    }
+    if let Some(slow_threshold) = env_var::<u64>("SLOW_DOMAIN_MILLIS") {
+        validator = validator.with_slow_threshold(std::time::Duration::from_millis(slow_threshold));
+    }
+    if let Some(quic_timeout) =
+        env_var::<u64>("QUIC_TIMEOUT_SECONDS").filter(|timeout| *timeout > 0)
+    {
+        validator = validator.with_quic_timeout(std::time::Duration::from_secs(quic_timeout));
+    }
+    if let Some(max_chain_depth) = env_var::<usize>("MAX_CHAIN_DEPTH") {
+        validator = validator.with_max_chain_depth(max_chain_depth);
+    }
+    if let Some(max_validity_days) = env_var::<i64>("MAX_VALIDITY_DAYS") {
+        validator = validator.with_max_validity_days(max_validity_days);
+    }
+    let distrusted_issuers = env_list("DISTRUSTED_ISSUERS");
+    if !distrusted_issuers.is_empty() {
+        validator = validator.with_distrusted_issuers(distrusted_issuers);
+    }
+    let allowed_issuers = env_list("ALLOWED_ISSUERS");
+    if !allowed_issuers.is_empty() {
+        validator = validator.with_allowed_issuers(allowed_issuers);
+    }
+    if let Some(probe_http) = env_var::<bool>("PROBE_HTTP") {
+        validator = validator.with_probe_http(probe_http);
+    }
+    if let Some(max_redirects) = env_var::<usize>("MAX_REDIRECTS") {
+        validator = validator.with_max_redirects(max_redirects);
+    }
+    if let Some(require_http2) = env_var::<bool>("REQUIRE_HTTP2") {
+        validator = validator.with_require_http2(require_http2);
+    }
+    if let Some(require_ocsp_stapling) = env_var::<bool>("REQUIRE_OCSP_STAPLING") {
+        validator = validator.with_require_ocsp_stapling(require_ocsp_stapling);
+    }
+    if let Some(fast_mode) = env_var::<bool>("FAST_MODE") {
+        validator = validator.with_fast_mode(fast_mode);
+    }
+    if let Some(require_complete_chain) = env_var::<bool>("REQUIRE_COMPLETE_CHAIN") {
+        validator = validator.with_require_complete_chain(require_complete_chain);
+    }
+    if let Some(min_rsa_bits) = env_var::<u32>("MIN_RSA_KEY_BITS") {
+        validator = validator.with_min_rsa_bits(min_rsa_bits);
+    }
+    if let Some(reject_weak_signatures) = env_var::<bool>("REJECT_WEAK_SIGNATURES") {
+        validator = validator.with_reject_weak_signatures(reject_weak_signatures);
+    }
+    if let Some(require_sct) = env_var::<bool>("REQUIRE_SCT") {
+        validator = validator.with_require_sct(require_sct);
+    }
+    if let Some(strict_basic_constraints) = env_var::<bool>("STRICT_BASIC_CONSTRAINTS") {
+        validator = validator.with_strict_basic_constraints(strict_basic_constraints);
+    }
+    if let Some(maintenance_window) =
+        env_var::<String>("MAINTENANCE_WINDOW").filter(|value| !value.trim().is_empty())
+    {
+        validator = validator.with_maintenance_window(maintenance_window.parse()?);
+    }
+    let resolver_kind =
+        resolver::parse_resolver_kind(env_var::<String>("DNS_RESOLVER").as_deref())?;
+    info!("Using DNS resolver {:?}", &resolver_kind);
+    // TLSA records are trusted only when DNSSEC validated
+    let dane = env_var::<bool>("DANE").unwrap_or(false);
+    validator = validator
+        .with_resolver(resolver::build_resolver(&resolver_kind, dane)?)
+        .with_dane(dane);
+    Ok(validator)
+}
+
+/// Parse a comma-separated list of domains
+fn parse_domain_list(value: &str) -> Vec<String> {
+    parse_list(&value.to_lowercase())
+}
+
+/// How statuses are treated as they are checked
+#[derive(Debug, Default)]
+struct CheckPolicy {
+    /// Suppress all failures, during a maintenance window
+    maintenance: bool,
+    /// Domains whose failures are suppressed, from `SUPPRESSED_DOMAINS`
+    suppressed_domains: Vec<String>,
+    /// Stop after the first critical failure
+    fail_fast: bool,
+    /// Error kinds classified as warnings, from `WARNING_ERROR_KINDS`
+    warning_kinds: Vec<String>,
+}
+
+impl CheckPolicy {
+    fn from_env(maintenance: bool, fail_fast: bool) -> Self {
+        CheckPolicy {
+            maintenance,
+            suppressed_domains: std::env::var("SUPPRESSED_DOMAINS")
+                .map(|value| parse_domain_list(&value))
+                .unwrap_or_default(),
+            fail_fast,
+            warning_kinds: warning_kinds(),
+        }
+    }
 
-    let content = object.body.collect().await?.to_vec();
+    /// Suppress failures during maintenance and of configured hosts,
+    /// keeping their actual state
+    fn suppress(&self, domain: &ConfigDomain, status: &mut Status) {
+        if status.valid {
+            return;
+        }
+        if self.maintenance {
+            status.suppressed = true;
+        }
+        if self
+            .suppressed_domains
+            .contains(&domain.host().to_lowercase())
+        {
+            info!("Suppressing failure of domain {}", &status.domain);
+            status.suppressed = true;
+        }
+    }
 
-    let lines: Vec<String> = str::from_utf8(&content)?
-        .split('\n')
-        .map(String::from)
-        .filter(|s| !s.is_empty())
-        .collect();
+    /// Whether `fail_fast` stops at a status: only critical failures count,
+    /// not suppressed failures or warnings
+    fn stops_at(&self, status: &Status) -> bool {
+        self.fail_fast && Severity::of(status, &self.warning_kinds) == Severity::Critical
+    }
+}
+
+/// Parse `MAX_CONCURRENCY` value, clamping it to a sane range
+fn parse_concurrency(value: Option<&str>) -> usize {
+    value
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .unwrap_or(DEFAULT_CONCURRENCY)
+        .clamp(1, MAX_CONCURRENCY)
+}
 
-    Ok(lines)
+/// Largest share of the time left before the deadline spent on the startup jitter
+const STARTUP_JITTER_SHARE: f64 = 0.1;
+
+/// Random delay before a run, up to `max_jitter_ms` and a share of `remaining`
+/// time, so scheduled invocations firing at the same moment do not hit shared
+/// hosts together
+fn startup_jitter<R: Rng>(rng: &mut R, max_jitter_ms: u64, remaining: Duration) -> Duration {
+    let max_jitter_ms =
+        max_jitter_ms.min(remaining.mul_f64(STARTUP_JITTER_SHARE).as_millis() as u64);
+    if max_jitter_ms == 0 {
+        return Duration::ZERO;
+    }
+    Duration::from_millis(rng.gen_range(0..=max_jitter_ms))
+}
+
+/// Time reserved after validation for sinks, X-Ray and the response
+const VALIDATION_DEADLINE_MARGIN: Duration = Duration::from_secs(10);
+
+/// Share of the remaining time reserved instead, if smaller, so short
+/// invocations still validate anything
+const VALIDATION_DEADLINE_SHARE: f64 = 0.2;
+
+/// Time left to validate domains before the invocation deadline
+fn validation_timeout(deadline: SystemTime, now: SystemTime) -> Duration {
+    let remaining = deadline.duration_since(now).unwrap_or_default();
+    let margin = VALIDATION_DEADLINE_MARGIN.min(remaining.mul_f64(VALIDATION_DEADLINE_SHARE));
+    remaining.saturating_sub(margin)
+}
+
+/// Status of a domain skipped when the deadline is reached
+fn not_checked(domain: &ConfigDomain) -> Status {
+    Status {
+        domain: domain.display_name(),
+        tier: domain.tier(),
+        valid: false,
+        error: "not checked: deadline".into(),
+        error_kind: Some("deadline".into()),
+        error_code: Some("DEADLINE".into()),
+        ..Default::default()
+    }
+}
+
+/// Validate up to `concurrency` domains at once, starting the next domain as
+/// soon as any check completes.
+/// Statuses are suppressed by `policy` and appended with their index in
+/// `domains` as checks complete, so they survive cancellation. With
+/// `fail_fast`, domains after the first critical failure are not checked.
+/// Returns whether the checks stopped early.
+async fn check_domains(
+    validator: Arc<Validator>,
+    domains: Vec<String>,
+    recorder: Option<Arc<xray::Recorder>>,
+    concurrency: usize,
+    policy: &CheckPolicy,
+    checked: &mut Vec<(usize, Status)>,
+) -> Result<bool, Error> {
+    let total = domains.len();
+    info!("Validating with concurrency {}", concurrency);
+
+    let mut checks = stream::iter(domains.into_iter().enumerate())
+        .map(|(index, domain)| {
+            let validator = validator.clone();
+            let recorder = recorder.clone();
+            tokio::task::spawn_blocking(move || {
+                let domain = ConfigDomain::new(domain);
+                let status = match recorder {
+                    Some(recorder) => {
+                        recorder.record(domain.host(), || check_domain(&validator, &domain))
+                    }
+                    None => check_domain(&validator, &domain),
+                };
+                (index, domain, status)
+            })
+        })
+        .buffer_unordered(concurrency.max(1));
+    while let Some(task) = checks.next().await {
+        let (index, domain, mut status) = task?;
+        policy.suppress(&domain, &mut status);
+        let stop = policy.stops_at(&status);
+        let failed = status.domain.clone();
+        checked.push((index, status));
+        if checked.len().is_multiple_of(concurrency.max(1)) {
+            info!("Checked {}/{} domains", checked.len(), total);
+        }
+        if stop && checked.len() < total {
+            warn!(
+                "Stopping after failed domain {}, {} of {} domains checked",
+                failed,
+                checked.len(),
+                total
+            );
+            return Ok(true);
+        }
+    }
+
+    let valid = checked.iter().filter(|(_, status)| status.valid).count();
+    info!(
+        "Checked {} domains: {} valid, {} invalid",
+        total,
+        valid,
+        total - valid
+    );
+    Ok(false)
+}
+
+/// Statuses of checked domains in the order of the config
+fn in_order(mut checked: Vec<(usize, Status)>) -> Vec<Status> {
+    checked.sort_by_key(|(index, _)| *index);
+    checked.into_iter().map(|(_, status)| status).collect()
+}
+
+/// Counts of domain states for the per-invocation summary
+#[derive(Debug, Default, PartialEq)]
+struct Summary {
+    total: usize,
+    valid: usize,
+    /// Certificates expiring soon, suppressed failures and other warnings
+    warning: usize,
+    /// All other failures
+    critical: usize,
+}
+
+fn summarize(statuses: &[Status], warning_kinds: &[String]) -> Summary {
+    let mut summary = Summary {
+        total: statuses.len(),
+        ..Default::default()
+    };
+    for status in statuses {
+        match Severity::of(status, warning_kinds) {
+            Severity::Ok => summary.valid += 1,
+            Severity::Warning => summary.warning += 1,
+            Severity::Critical => summary.critical += 1,
+        }
+    }
+    summary
+}
+
+/// Whether any status is a critical failure, which stops `fail_fast` checks
+fn has_critical(statuses: &[Status]) -> bool {
+    let warning_kinds = warning_kinds();
+    statuses
+        .iter()
+        .any(|status| Severity::of(status, &warning_kinds) == Severity::Critical)
+}
+
+/// Error kinds counted as warnings, configured with `WARNING_ERROR_KINDS`
+/// shared with the reporter
+fn warning_kinds() -> Vec<String> {
+    env_var::<String>("WARNING_ERROR_KINDS")
+        .map(|value| parse_warning_kinds(&value))
+        .unwrap_or_default()
+}
+
+/// Validate domains as of `now` and apply suppressions.
+/// Domains not checked within `timeout` are reported as such, while domains
+/// skipped by `fail_fast` are left out.
+/// Returns statuses and whether checks ran during maintenance.
+async fn check_all(
+    domains: Vec<String>,
+    now: DateTime<Utc>,
+    recorder: Option<Arc<xray::Recorder>>,
+    timeout: Option<Duration>,
+    fail_fast: bool,
+) -> Result<(Vec<Status>, bool), Error> {
+    let validator = build_validator(now)?;
+    let maintenance = validator.in_maintenance();
+    if maintenance {
+        info!("Running during maintenance window, suppressing all failures");
+    }
+    let policy = CheckPolicy::from_env(maintenance, fail_fast);
+    let concurrency = parse_concurrency(std::env::var("MAX_CONCURRENCY").ok().as_deref());
+    let mut checked = Vec::with_capacity(domains.len());
+    let checks = check_domains(
+        Arc::new(validator),
+        domains.clone(),
+        recorder,
+        concurrency,
+        &policy,
+        &mut checked,
+    );
+    match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, checks).await {
+            Ok(res) => {
+                res?;
+            }
+            Err(_) => {
+                warn!(
+                    "Deadline reached after {:?}, {} of {} domains checked",
+                    timeout,
+                    checked.len(),
+                    domains.len()
+                );
+                let done: HashSet<usize> = checked.iter().map(|(index, _)| *index).collect();
+                let remaining = domains
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(index, _)| !done.contains(index));
+                let skipped: Vec<_> = remaining
+                    .map(|(index, domain)| {
+                        let domain = ConfigDomain::new(domain);
+                        let mut status = not_checked(&domain);
+                        policy.suppress(&domain, &mut status);
+                        (index, status)
+                    })
+                    .collect();
+                checked.extend(skipped);
+            }
+        },
+        None => {
+            checks.await?;
+        }
+    }
+    Ok((in_order(checked), maintenance))
 }
 
 /// This is the main body for the function.
@@ -81,45 +1152,1401 @@ async fn parse_domains(s3_config_location: &str) -> Result<Vec<String>, Error> {
 /// There are some code example in the following URLs:
 /// - https://github.com/awslabs/aws-lambda-rust-runtime/tree/main/examples
 /// - https://github.com/aws-samples/serverless-rust-demo/
-async fn function_handler(event: LambdaEvent<Request>) -> Result<Response, Error> {
+async fn function_handler(event: LambdaEvent<Event>) -> Result<Output, Error> {
+    let (event, context) = event.into_parts();
+    match event {
+        Event::Warmup(_) => {
+            info!("Warmup ping, skipping checks");
+            Ok(Output::Direct(Response {
+                req_id: context.request_id,
+                ..Default::default()
+            }))
+        }
+        Event::Direct(request) => {
+            // Chunks of a fan-out are already staggered by the dispatching run
+            if request.domains.is_none() {
+                delay_start(context.deadline()).await;
+            }
+            Ok(Output::Direct(monitor(request, context).await?))
+        }
+        Event::Http(http) => Ok(Output::Http(dashboard(http, context).await?)),
+    }
+}
+
+/// Sleep for a random jitter up to `STARTUP_JITTER_MS` before scheduled checks
+async fn delay_start(deadline: SystemTime) {
+    if let Some(max_jitter_ms) = env_var::<u64>("STARTUP_JITTER_MS") {
+        let remaining = deadline
+            .duration_since(SystemTime::now())
+            .unwrap_or_default();
+        let jitter = startup_jitter(&mut rand::thread_rng(), max_jitter_ms, remaining);
+        info!("Delaying start by {:?}", jitter);
+        tokio::time::sleep(jitter).await;
+    }
+}
+
+/// Run the check for a Function URL request, configured with `S3_CONFIG_LOCATION`
+async fn dashboard(http: http::HttpRequest, context: Context) -> Result<http::HttpResponse, Error> {
+    if http.method() != "GET" {
+        return Ok(http::HttpResponse::error(405, "Method not allowed"));
+    }
+    let Some(s3_config_location) = env_var::<String>("S3_CONFIG_LOCATION") else {
+        return Ok(http::HttpResponse::error(
+            500,
+            "S3_CONFIG_LOCATION is not configured",
+        ));
+    };
+    let request = Request {
+        s3_config_location,
+        ..Default::default()
+    };
+    let response = monitor(request, context).await?;
+    Ok(match http.query("format") {
+        Some("html") => http::HttpResponse::html(output::render_html(&response.statuses)),
+        Some("prometheus") => {
+            http::HttpResponse::prometheus(output::render_prometheus(&response.statuses))
+        }
+        _ => http::HttpResponse::json(serde_json::to_string(&response)?),
+    })
+}
+
+/// Check domains of a direct invocation
+async fn monitor(request: Request, context: Context) -> Result<Response, Error> {
+    let now = Utc::now();
+    let as_of = request.as_of.unwrap_or(now);
+    if as_of != now {
+        info!("Validating as of {}", as_of);
+    }
+
+    // Certificate from the request is validated without any network
+    if let Some(content) = request.inline_certificate() {
+        let validator = build_validator(as_of)?;
+        let res = content.and_then(|content| validator.validate_bytes(content));
+        return Ok(Response {
+            req_id: context.request_id,
+            statuses: vec![domain_status(INLINE_DOMAIN.into(), res)],
+            checked_at: Some(now),
+            ..Default::default()
+        });
+    }
+
+    // Chunk of a larger list only reports statuses back to the dispatching invocation
+    if let Some(domains) = request.domains {
+        let fail_fast = request.fail_fast || env_var::<bool>("FAIL_FAST").unwrap_or(false);
+        let total = domains.len();
+        info!("Checking a chunk of {} domains", total);
+        let timeout = validation_timeout(context.deadline(), SystemTime::now());
+        let (statuses, maintenance) =
+            check_all(domains, as_of, None, Some(timeout), fail_fast).await?;
+        return Ok(Response {
+            req_id: context.request_id,
+            stopped_early: statuses.len() < total,
+            statuses,
+            maintenance,
+            checked_at: Some(now),
+            ..Default::default()
+        });
+    }
+
     // Extract some useful info from the request
-    let s3_config_location = event.payload.s3_config_location;
+    let s3_config_location =
+        config_location(&request.s3_config_location, request.environment.as_deref())?;
+    let locations = split_locations(&s3_config_location);
 
-    let domains: Vec<String> = parse_domains(&s3_config_location).await?;
+    let sse_kms_key_id = env_var::<String>("SSE_KMS_KEY_ID");
 
-    let max_expiration: u64 = 10;
+    let recorder = xray::Recorder::from_env(context.xray_trace_id.as_deref()).map(Arc::new);
 
-    let validator = Validator::new(Utc::now(), max_expiration);
+    let aws_config = load_aws_config().await;
+    let mut sources = Vec::with_capacity(locations.len());
+    let mut config_errors = Vec::new();
+    let mut first_error = None;
+    for location in &locations {
+        let fetch = fetch_domains(&aws_config, location, sse_kms_key_id.as_deref());
+        let res = match &recorder {
+            Some(recorder) => recorder.record_async("s3_fetch", fetch).await,
+            None => fetch.await,
+        };
+        match res {
+            Ok(lines) => {
+                let (domains, errors) = if config::is_structured(location) {
+                    config::parse_structured(lines)
+                } else {
+                    (lines, Vec::new())
+                };
+                info!("Read {} domains from {}", domains.len(), location);
+                config_errors.extend(errors);
+                sources.push(domains);
+            }
+            Err(err) => {
+                warn!("Cannot read config {}: {}", location, err);
+                config_errors.push(unreadable_config(location, &err));
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+    // Fail only if no config could be read at all
+    if sources.is_empty() {
+        return Err(first_error
+            .unwrap_or_else(|| MonitorError::Config("No S3 config location".into()).into()));
+    }
+    let mut domains = expand_domains(merge_domains(sources));
+    // Vary the order of hosts between runs
+    if env_var::<bool>("SHUFFLE_DOMAINS").unwrap_or(false) {
+        domains.shuffle(&mut rand::thread_rng());
+    }
 
-    let statuses: Vec<Status> = domains
+    if !config_errors.is_empty() {
+        info!("Skipping {} malformed config lines", config_errors.len());
+    }
+    if let Some(status) = empty_config(&domains, &config_errors, &s3_config_location) {
+        warn!("No domains to check in {}", s3_config_location);
+        config_errors.push(status);
+    }
+    // Bundles from Secrets Manager are validated without any handshake
+    let (secret_domains, mut domains): (Vec<String>, Vec<String>) = domains
         .into_iter()
-        .map(|domain| match validator.validate_domain(&domain) {
-            Ok(()) => Status {
-                domain: domain.to_string(),
-                valid: true,
-                error: String::new(),
-            },
-            Err(error) => Status {
-                domain: domain.to_string(),
-                valid: false,
-                error: error.to_string(),
-            },
-        })
-        .collect();
+        .partition(|domain| secrets::is_secret(domain));
+    let secret_statuses = if secret_domains.is_empty() {
+        Vec::new()
+    } else {
+        let client = aws_sdk_secretsmanager::Client::new(&aws_config);
+        secrets::check_secrets(&client, &build_validator(as_of)?, secret_domains).await
+    };
+    // Skip domains recently confirmed healthy, unless back-testing
+    let schedule_table = env_var::<String>("SCHEDULE_TABLE").filter(|_| request.as_of.is_none());
+    let mut cached = Vec::new();
+    if let Some(schedule_table) = &schedule_table {
+        let client = aws_sdk_dynamodb::Client::new(&aws_config);
+        // Certificates within their expiration threshold are checked every run
+        let validator = build_validator(now)?;
+        let threshold_days = |domain: &str| {
+            let tier = domain
+                .parse::<DomainEntry>()
+                .ok()
+                .and_then(|entry| entry.tier);
+            validator.max_expiration_for(tier.as_deref())
+        };
+        (domains, cached) =
+            schedule::skip_recent(&client, schedule_table, domains, now, threshold_days).await;
+    }
+    let fail_fast = request.fail_fast || env_var::<bool>("FAIL_FAST").unwrap_or(false);
+    let total = domains.len();
+    // Check huge lists in several invocations of this function
+    let fan_out = fanout::plan(
+        domains.clone(),
+        env_var::<usize>("MAX_DOMAINS_PER_INVOCATION").unwrap_or(0),
+    );
+    let local_total = fan_out.local.len();
+    // Invoke the same version or alias of the function as this invocation
+    let dispatch = {
+        let client = aws_sdk_lambda::Client::new(&aws_config);
+        let function = context.invoked_function_arn.clone();
+        let timeout = validation_timeout(context.deadline(), SystemTime::now());
+        let as_of = request.as_of;
+        async move {
+            fanout::dispatch(
+                &client,
+                function,
+                fan_out.dispatched,
+                as_of,
+                fail_fast,
+                timeout,
+            )
+            .await
+        }
+    };
+    // Running invocations cannot be cancelled, so with fail_fast chunks
+    // are dispatched only after local domains passed
+    let (dispatched, dispatch) = if fail_fast {
+        (None, Some(dispatch))
+    } else {
+        (Some(tokio::spawn(dispatch)), None)
+    };
+    let (mut statuses, maintenance) = check_all(
+        fan_out.local,
+        as_of,
+        recorder.clone(),
+        Some(validation_timeout(context.deadline(), SystemTime::now())),
+        fail_fast,
+    )
+    .await?;
+    // Statuses follow the order of domains, so dispatched chunks go after local ones
+    match (dispatched, dispatch) {
+        (Some(dispatched), _) => statuses.extend(dispatched.await?),
+        (None, Some(dispatch)) if statuses.len() == local_total => statuses.extend(dispatch.await),
+        _ => {}
+    }
+    let stopped_early = statuses.len() < total;
+    let mut sinks = sink::Sinks::default();
+    if let Some(schedule_table) = schedule_table {
+        let client = aws_sdk_dynamodb::Client::new(&aws_config);
+        let statuses = statuses.clone();
+        sinks.spawn("schedule", async move {
+            schedule::remember(&client, &schedule_table, &domains, &statuses, now).await
+        });
+    }
+    statuses.extend(
+        cached
+            .into_iter()
+            .map(|(domain, last)| cached_status(domain, &last, now)),
+    );
+    statuses.extend(secret_statuses);
+    statuses.extend(config_errors);
+
+    if let Some(fingerprint_table) = env_var::<String>("FINGERPRINT_TABLE") {
+        let client = aws_sdk_dynamodb::Client::new(&aws_config);
+        if let Err(err) =
+            rotation::detect_rotations(&client, &fingerprint_table, &mut statuses, maintenance)
+                .await
+        {
+            warn!("Cannot detect certificate rotations: {}", err);
+        }
+    }
+
+    if let Some(history_table) = env_var::<String>("HISTORY_TABLE") {
+        let client = aws_sdk_dynamodb::Client::new(&aws_config);
+        let statuses = statuses.clone();
+        let req_id = context.request_id.clone();
+        sinks.spawn("history", async move {
+            history::write_history(&client, &history_table, &statuses, now, &req_id).await
+        });
+    }
+
+    if let Some(csv_location) = env_var::<String>("CSV_EXPORT_LOCATION") {
+        let client = Client::new(&aws_config);
+        let csv = output::render_csv(&statuses, &warning_kinds());
+        sinks.spawn("csv", async move {
+            export_csv(&client, &csv_location, csv).await
+        });
+    }
+
+    let buckets = histogram(&statuses);
+    info!("Days remaining histogram: {:?}", &buckets);
+
+    let summary = common_failure(&statuses).map(|failure| failure.to_string());
+    if let Some(summary) = &summary {
+        info!("Collapsed failures: {}", summary);
+    }
+
+    // Canonical line for CloudWatch metric filters
+    let summary_counts = summarize(&statuses, &warning_kinds());
+    info!(
+        total = summary_counts.total,
+        valid = summary_counts.valid,
+        warning = summary_counts.warning,
+        critical = summary_counts.critical,
+        "summary"
+    );
+
+    let recheck_at = recommended_recheck_at(&statuses, now);
+    info!("Recommended next check at {}", recheck_at);
 
     // Prepare the response
     let resp = Response {
-        req_id: event.context.request_id,
+        req_id: context.request_id.clone(),
         statuses,
+        buckets,
+        maintenance,
+        summary,
+        checked_at: Some(now),
+        recommended_recheck_at: Some(recheck_at),
+        stopped_early,
+        sink_errors: Vec::new(),
     };
 
+    if let Some(report_location) = env_var::<String>("REPORT_EXPORT_LOCATION") {
+        let client = Client::new(&aws_config);
+        let json = serde_json::to_string(&resp)?;
+        sinks.spawn("report", async move {
+            export_report(&client, &report_location, now, json).await
+        });
+    }
+
+    // Make sure everything is sent before the runtime freezes the function
+    let resp = deliver(
+        sinks,
+        sink::flush_timeout(context.deadline(), SystemTime::now()),
+        resp,
+    )
+    .await;
+
+    if let Some(recorder) = &recorder {
+        if let Err(err) = recorder.send() {
+            info!("Cannot send X-Ray subsegments: {}", err);
+        }
+    }
+
     // Return `Response` (it will be serialized to JSON automatically by the runtime)
     Ok(resp)
 }
 
+/// Wait for sinks and record their failures in the response, so an unavailable
+/// sink does not fail the invocation after domains were checked
+async fn deliver(sinks: sink::Sinks, timeout: Duration, mut resp: Response) -> Response {
+    resp.sink_errors = sinks.flush(timeout).await;
+    resp
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Error> {
+    // Arguments mean a local run, which prints results instead of logs
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        let code = cli::run(&args).await?;
+        std::process::exit(code);
+    }
+
     tracing::init_default_subscriber();
 
     run(service_fn(function_handler)).await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_s3_location() {
+        let cases = [
+            ("s3://bucket/config.txt", "bucket", "config.txt"),
+            (
+                "s3://my.bucket/path/to/config.txt",
+                "my.bucket",
+                "path/to/config.txt",
+            ),
+            (
+                "https://bucket.s3.amazonaws.com/path/config.txt",
+                "bucket",
+                "path/config.txt",
+            ),
+            (
+                "https://my.bucket.s3.eu-west-1.amazonaws.com/config%20file.txt",
+                "my.bucket",
+                "config file.txt",
+            ),
+            (
+                "https://bucket.s3-eu-west-1.amazonaws.com/config.txt",
+                "bucket",
+                "config.txt",
+            ),
+            (
+                "https://s3.eu-west-1.amazonaws.com/bucket/path/config.txt",
+                "bucket",
+                "path/config.txt",
+            ),
+        ];
+        for (location, bucket, key) in cases {
+            assert_eq!(
+                parse_s3_location(location).expect(location),
+                (bucket.to_string(), key.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_s3_location_wrong() {
+        for location in [
+            "bucket/config.txt",
+            "s3://bucket",
+            "s3://bucket/",
+            "https://example.com/config.txt",
+            "https://s3.amazonaws.com/bucket",
+            "ftp://bucket/config.txt",
+        ] {
+            assert!(
+                matches!(parse_s3_location(location), Err(MonitorError::Config(_))),
+                "{}",
+                location
+            );
+        }
+    }
+
+    #[test]
+    fn test_config_source() {
+        assert_eq!(
+            config_source("s3://bucket/config.txt").unwrap(),
+            ConfigSource::S3("s3://bucket/config.txt")
+        );
+        assert_eq!(
+            config_source("https://bucket.s3.amazonaws.com/config.txt").unwrap(),
+            ConfigSource::S3("https://bucket.s3.amazonaws.com/config.txt")
+        );
+        for (location, name) in [
+            ("ssm://domains", "domains"),
+            ("ssm:///prod/domains", "/prod/domains"),
+            ("ssm://prod/domains", "/prod/domains"),
+            ("ssm://prod/domains/", "/prod/domains"),
+        ] {
+            assert_eq!(
+                config_source(location).unwrap(),
+                ConfigSource::Ssm(name.into()),
+                "{}",
+                location
+            );
+        }
+        for location in ["ssm://", "ssm:///", "ssm://///"] {
+            assert!(
+                matches!(config_source(location), Err(MonitorError::Config(_))),
+                "{}",
+                location
+            );
+        }
+    }
+
+    #[test]
+    fn test_config_location() {
+        assert_eq!(
+            config_location("s3://bucket/domains-{env}.txt", Some("prod")).unwrap(),
+            "s3://bucket/domains-prod.txt"
+        );
+        assert_eq!(
+            config_location(
+                "s3://bucket/{env}/a.txt, s3://bucket/{env}/b.txt",
+                Some("staging")
+            )
+            .unwrap(),
+            "s3://bucket/staging/a.txt, s3://bucket/staging/b.txt"
+        );
+        assert_eq!(
+            config_location("s3://bucket/domains.txt", Some("prod")).unwrap(),
+            "s3://bucket/domains.txt"
+        );
+        assert_eq!(
+            config_location("s3://bucket/domains.txt", None).unwrap(),
+            "s3://bucket/domains.txt"
+        );
+        for environment in [None, Some(""), Some("../prod"), Some("prod staging")] {
+            assert!(
+                matches!(
+                    config_location("s3://bucket/domains-{env}.txt", environment),
+                    Err(MonitorError::Config(_))
+                ),
+                "{:?}",
+                environment
+            );
+        }
+
+        let request: Request = serde_json::from_str(
+            r#"{"s3_config_location": "s3://bucket/domains-{env}.txt", "environment": "prod"}"#,
+        )
+        .unwrap();
+        assert_eq!(request.environment.as_deref(), Some("prod"));
+    }
+
+    #[test]
+    fn test_request_as_of() {
+        let request: Request =
+            serde_json::from_str(r#"{"s3_config_location": "s3://bucket/config.txt"}"#).unwrap();
+        assert_eq!(request.as_of, None);
+
+        let request: Request = serde_json::from_str(
+            r#"{"s3_config_location": "s3://bucket/config.txt", "as_of": "2031-05-01T00:00:00Z"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            request.as_of.map(|as_of| as_of.to_rfc3339()).as_deref(),
+            Some("2031-05-01T00:00:00+00:00")
+        );
+    }
+
+    #[test]
+    fn test_event_dispatch() {
+        let event: Event =
+            serde_json::from_str(r#"{"s3_config_location": "s3://bucket/config.txt"}"#).unwrap();
+        assert!(
+            matches!(event, Event::Direct(request) if request.s3_config_location == "s3://bucket/config.txt")
+        );
+
+        let event: Event = serde_json::from_str(
+            r#"{
+                "version": "2.0",
+                "rawPath": "/",
+                "headers": {"accept": "text/html"},
+                "requestContext": {"http": {"method": "GET", "path": "/"}},
+                "isBase64Encoded": false
+            }"#,
+        )
+        .unwrap();
+        assert!(matches!(event, Event::Http(http) if http.method() == "GET"));
+
+        let event: Event = serde_json::from_str(
+            r#"{"httpMethod": "GET", "path": "/", "requestContext": {"stage": "prod"}}"#,
+        )
+        .unwrap();
+        assert!(matches!(event, Event::Http(_)));
+    }
+
+    #[test]
+    fn test_warmup_event() {
+        let is_warmup = |event: serde_json::Value| {
+            matches!(serde_json::from_value(event).unwrap(), Event::Warmup(_))
+        };
+        assert!(is_warmup(serde_json::json!({ "warmup": true })));
+        assert!(is_warmup(
+            serde_json::json!({ "source": "serverless-plugin-warmup" })
+        ));
+        assert!(is_warmup(serde_json::json!({
+            "version": "0",
+            "id": "53dc4d37-cffa-4f76-80c9-8b7d4a4d2eaa",
+            "detail-type": "Scheduled Event",
+            "source": "aws.events",
+            "account": "123456789012",
+            "time": "2024-05-01T00:00:00Z",
+            "region": "us-east-1",
+            "resources": ["arn:aws:events:us-east-1:123456789012:rule/warmer"],
+            "detail": {}
+        })));
+
+        assert!(!is_warmup(serde_json::json!({ "warmup": false })));
+        assert!(!is_warmup(serde_json::json!({})));
+        assert!(!is_warmup(serde_json::json!({
+            "s3_config_location": "s3://bucket/config.txt"
+        })));
+        assert!(!is_warmup(serde_json::json!({
+            "source": "aws.s3",
+            "detail-type": "Object Created"
+        })));
+        assert!(!is_warmup(serde_json::json!({
+            "requestContext": {"http": {"method": "GET", "path": "/"}}
+        })));
+    }
+
+    #[test]
+    fn test_output_shape() {
+        let output = serde_json::to_value(Output::Direct(Response {
+            req_id: "42".into(),
+            ..Default::default()
+        }))
+        .unwrap();
+        assert_eq!(output["req_id"], "42");
+
+        let output =
+            serde_json::to_value(Output::Http(http::HttpResponse::json("{}".into()))).unwrap();
+        assert_eq!(output["statusCode"], 200);
+        assert_eq!(output["body"], "{}");
+    }
+
+    #[test]
+    fn test_split_locations() {
+        assert_eq!(
+            split_locations("s3://bucket/baseline.txt"),
+            vec!["s3://bucket/baseline.txt"]
+        );
+        assert_eq!(
+            split_locations("s3://bucket/baseline.txt, s3://team/own.jsonl s3://team/more.txt"),
+            vec![
+                "s3://bucket/baseline.txt",
+                "s3://team/own.jsonl",
+                "s3://team/more.txt"
+            ]
+        );
+        assert!(split_locations(" , ").is_empty());
+    }
+
+    #[test]
+    fn test_merge_domains() {
+        let baseline = vec!["example.com".to_string(), "example.org".to_string()];
+        let own = vec![
+            "example.org".to_string(),
+            "team.example.com".to_string(),
+            "team.example.com".to_string(),
+        ];
+        assert_eq!(
+            merge_domains(vec![baseline, own]),
+            vec!["example.com", "example.org", "team.example.com"]
+        );
+        assert!(merge_domains(vec![]).is_empty());
+    }
+
+    #[test]
+    fn test_empty_config() {
+        let status = empty_config(&[], &[], "s3://bucket/domains.txt").unwrap();
+        assert_eq!(status.domain, "s3://bucket/domains.txt");
+        assert!(!status.valid);
+        assert_eq!(status.error, "config error: no domains to check");
+        assert_eq!(status.error_kind.as_deref(), Some("config"));
+
+        assert!(empty_config(&["example.com".to_string()], &[], "s3://bucket/a").is_none());
+        let malformed = Status {
+            domain: "{".into(),
+            ..Default::default()
+        };
+        assert!(empty_config(&[], &[malformed], "s3://bucket/a.jsonl").is_none());
+    }
+
+    #[test]
+    fn test_unreadable_config() {
+        let err: Error = "NoSuchKey".into();
+        let status = unreadable_config("s3://bucket/team.txt", &err);
+        assert_eq!(status.domain, "s3://bucket/team.txt");
+        assert!(!status.valid);
+        assert_eq!(status.error, "config error: cannot read config: NoSuchKey");
+        assert_eq!(status.error_kind.as_deref(), Some("config"));
+    }
+
+    #[test]
+    fn test_inline_certificate_der() {
+        let encoded = base64::engine::general_purpose::STANDARD
+            .encode(include_bytes!("./data/cert-2031.der"));
+        let request: Request =
+            serde_json::from_value(serde_json::json!({ "cert_der_base64": encoded })).unwrap();
+        let content = request.inline_certificate().unwrap().unwrap();
+        assert_eq!(content, include_bytes!("./data/cert-2031.der"));
+
+        let now = DateTime::parse_from_rfc3339("2024-05-01T00:00:00Z")
+            .unwrap()
+            .to_utc();
+        let validator = Validator::new(now, 10).unwrap();
+        let status = domain_status(INLINE_DOMAIN.into(), validator.validate_bytes(content));
+        assert!(status.valid);
+        assert_eq!(status.domain, "inline");
+        assert_eq!(
+            status.not_after.map(|date| date.to_rfc3339()).as_deref(),
+            Some("2031-05-03T07:00:00+00:00")
+        );
+    }
+
+    struct RecordingResolver {
+        hosts: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl resolver::Resolver for RecordingResolver {
+        fn resolve(&self, host: &str, _port: u16) -> std::io::Result<Vec<std::net::SocketAddr>> {
+            self.hosts.lock().unwrap().push(host.to_string());
+            Err(std::io::ErrorKind::NotFound.into())
+        }
+    }
+
+    #[test]
+    fn test_check_domain_alias() {
+        let hosts = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let validator = Validator::new(Utc::now(), 10)
+            .unwrap()
+            .with_resolver(Box::new(RecordingResolver {
+                hosts: hosts.clone(),
+            }));
+        let domain = ConfigDomain::new(
+            r#""Payments API" = api-internal-7.example.com tier=critical"#.into(),
+        );
+        let status = check_domain(&validator, &domain);
+        assert_eq!(status.domain, "Payments API");
+        assert_eq!(status.tier.as_deref(), Some("critical"));
+        assert!(!status.valid);
+        assert_eq!(status.error_code.as_deref(), Some("DNS"));
+        assert_eq!(*hosts.lock().unwrap(), vec!["api-internal-7.example.com"]);
+        // Domains left unchecked are reported under the same name
+        let status = not_checked(&domain);
+        assert_eq!(status.domain, "Payments API");
+        assert_eq!(status.tier.as_deref(), Some("critical"));
+
+        let status = check_domain(&validator, &ConfigDomain::new("example.com".into()));
+        assert_eq!(status.domain, "example.com");
+        assert!(status.duration_ms.is_some());
+    }
+
+    #[test]
+    fn test_inline_certificate_pem() {
+        let pem = include_str!("./data/chain-expired.pem");
+        let request: Request =
+            serde_json::from_value(serde_json::json!({ "cert_pem": pem })).unwrap();
+        let content = request.inline_certificate().unwrap().unwrap();
+        let now = DateTime::parse_from_rfc3339("2024-05-01T00:00:00Z")
+            .unwrap()
+            .to_utc();
+        let validator = Validator::new(now, 10).unwrap();
+        let status = domain_status(INLINE_DOMAIN.into(), validator.validate_bytes(content));
+        assert!(!status.valid);
+        assert_eq!(status.error_kind.as_deref(), Some("expired_ago"));
+    }
+
+    #[test]
+    fn test_inline_certificate_invalid() {
+        let request: Request =
+            serde_json::from_value(serde_json::json!({ "cert_der_base64": "not base64!" }))
+                .unwrap();
+        assert!(matches!(
+            request.inline_certificate(),
+            Some(Err(MonitorError::Config(_)))
+        ));
+
+        let request: Request =
+            serde_json::from_str(r#"{"s3_config_location": "s3://bucket/config.txt"}"#).unwrap();
+        assert!(request.inline_certificate().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_check_all_as_of() {
+        let domain = format!(
+            "file://{}/src/data/cert-2031.der",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let as_of = |date: &str| DateTime::parse_from_rfc3339(date).unwrap().to_utc();
+
+        let (statuses, _) = check_all(
+            vec![domain.clone()],
+            as_of("2024-05-01T00:00:00Z"),
+            None,
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+        assert!(statuses[0].valid);
+
+        // Certificate expires at 2031-05-03T07:00:00Z
+        let (statuses, _) = check_all(
+            vec![domain],
+            as_of("2031-04-30T07:00:00Z"),
+            None,
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+        assert!(!statuses[0].valid);
+        assert_eq!(statuses[0].error_kind.as_deref(), Some("expired"));
+        assert_eq!(statuses[0].days_remaining, Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_check_domains_fail_fast() {
+        let valid = format!(
+            "file://{}/src/data/cert-2031.der",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let domains = vec![
+            valid.clone(),
+            "file:///nonexistent.pem".to_string(),
+            valid.clone(),
+            valid,
+        ];
+        let now = DateTime::parse_from_rfc3339("2024-05-01T00:00:00Z")
+            .unwrap()
+            .to_utc();
+        let validator = Arc::new(Validator::new(now, 10).unwrap());
+        let fail_fast = CheckPolicy {
+            fail_fast: true,
+            ..Default::default()
+        };
+
+        let mut checked = Vec::new();
+        let stopped = check_domains(
+            validator.clone(),
+            domains.clone(),
+            None,
+            1,
+            &fail_fast,
+            &mut checked,
+        )
+        .await
+        .unwrap();
+        assert!(stopped);
+        let valid: Vec<bool> = in_order(checked)
+            .iter()
+            .map(|status| status.valid)
+            .collect();
+        assert_eq!(valid, vec![true, false]);
+
+        // Concurrent checks stop at the failed domain, before all domains are checked
+        let mut checked = Vec::new();
+        let stopped = check_domains(
+            validator.clone(),
+            domains.clone(),
+            None,
+            2,
+            &fail_fast,
+            &mut checked,
+        )
+        .await
+        .unwrap();
+        assert!(stopped);
+        assert!(checked.len() < 4);
+        assert!(checked
+            .iter()
+            .any(|(index, status)| *index == 1 && !status.valid));
+
+        // Suppressed failures and warnings do not stop checks
+        for policy in [
+            CheckPolicy {
+                suppressed_domains: vec!["file:///nonexistent.pem".into()],
+                ..fail_fast
+            },
+            CheckPolicy {
+                maintenance: true,
+                fail_fast: true,
+                ..Default::default()
+            },
+        ] {
+            let mut checked = Vec::new();
+            let stopped = check_domains(
+                validator.clone(),
+                domains.clone(),
+                None,
+                1,
+                &policy,
+                &mut checked,
+            )
+            .await
+            .unwrap();
+            assert!(!stopped, "{:?}", policy);
+            let statuses = in_order(checked);
+            assert_eq!(statuses.len(), 4);
+            assert!(statuses[1].suppressed);
+        }
+
+        let mut checked = Vec::new();
+        let stopped = check_domains(
+            validator,
+            domains,
+            None,
+            3,
+            &CheckPolicy::default(),
+            &mut checked,
+        )
+        .await
+        .unwrap();
+        assert!(!stopped);
+        let valid: Vec<bool> = in_order(checked)
+            .iter()
+            .map(|status| status.valid)
+            .collect();
+        assert_eq!(valid, vec![true, false, true, true]);
+    }
+
+    #[test]
+    fn test_check_policy_stops_at() {
+        let policy = CheckPolicy {
+            fail_fast: true,
+            warning_kinds: vec!["dns".into()],
+            ..Default::default()
+        };
+        let failed = |error_kind: &str| Status {
+            domain: "example.com".into(),
+            valid: false,
+            error_kind: Some(error_kind.into()),
+            ..Default::default()
+        };
+        assert!(policy.stops_at(&failed("network")));
+        assert!(!policy.stops_at(&failed("expired")));
+        assert!(!policy.stops_at(&failed("dns")));
+        assert!(!policy.stops_at(&Status {
+            suppressed: true,
+            ..failed("network")
+        }));
+        assert!(!CheckPolicy::default().stops_at(&failed("network")));
+    }
+
+    #[test]
+    fn test_startup_jitter() {
+        use rand::SeedableRng;
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        let remaining = Duration::from_secs(900);
+        assert_eq!(startup_jitter(&mut rng, 0, remaining), Duration::ZERO);
+        for _ in 0..1000 {
+            assert!(startup_jitter(&mut rng, 500, remaining) <= Duration::from_millis(500));
+        }
+        // Short invocations spend at most a tenth of their time waiting
+        for _ in 0..1000 {
+            assert!(
+                startup_jitter(&mut rng, 60_000, Duration::from_secs(5))
+                    <= Duration::from_millis(500)
+            );
+        }
+        assert_eq!(
+            startup_jitter(&mut rng, 500, Duration::ZERO),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn test_shuffle_domains_seeded() {
+        use rand::SeedableRng;
+
+        let domains: Vec<String> = (0..20).map(|i| format!("d{}.com", i)).collect();
+        let shuffled = |seed| {
+            let mut domains = domains.clone();
+            domains.shuffle(&mut rand::rngs::StdRng::seed_from_u64(seed));
+            domains
+        };
+        assert_eq!(shuffled(7), shuffled(7));
+        assert_ne!(shuffled(7), shuffled(8));
+        assert_ne!(shuffled(7), domains);
+        let mut sorted = shuffled(7);
+        sorted.sort_by_key(|domain| domain[1..domain.len() - 4].parse::<usize>().unwrap());
+        assert_eq!(sorted, domains);
+    }
+
+    #[test]
+    fn test_validation_timeout() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        assert_eq!(
+            validation_timeout(now + Duration::from_secs(900), now),
+            Duration::from_secs(890)
+        );
+        // Short deadlines keep a fifth of the time for the rest of the run
+        assert_eq!(
+            validation_timeout(now + Duration::from_secs(5), now),
+            Duration::from_secs(4)
+        );
+        assert_eq!(
+            validation_timeout(now + Duration::from_secs(30), now),
+            Duration::from_secs(24)
+        );
+        assert_eq!(
+            validation_timeout(now + Duration::from_secs(60), now),
+            Duration::from_secs(50)
+        );
+        assert_eq!(
+            validation_timeout(now - Duration::from_secs(1), now),
+            Duration::ZERO
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_lines_across_chunks() {
+        let domains: Vec<String> = (0..10000)
+            .map(|i| format!("domain-{}.example.com", i))
+            .collect();
+        let content = domains.join("\n") + "\n\n";
+        // Small buffer splits lines across many reads
+        let reader = tokio::io::BufReader::with_capacity(7, content.as_bytes());
+        assert_eq!(read_lines(reader).await.unwrap(), domains);
+    }
+
+    #[tokio::test]
+    async fn test_read_lines_crlf() {
+        let content = "a.com\r\n\r\nb.com";
+        assert_eq!(
+            read_lines(content.as_bytes()).await.unwrap(),
+            vec!["a.com".to_string(), "b.com".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_lines_invalid_utf8() {
+        let content: &[u8] = b"a.com\n\xff\xfe\n";
+        assert!(read_lines(content).await.is_err());
+    }
+
+    #[test]
+    fn test_summarize() {
+        let statuses = vec![
+            Status {
+                domain: "a.com".into(),
+                valid: true,
+                ..Default::default()
+            },
+            Status {
+                domain: "b.com".into(),
+                valid: true,
+                ..Default::default()
+            },
+            Status {
+                domain: "expiring.com".into(),
+                error_kind: Some("expired".into()),
+                ..Default::default()
+            },
+            Status {
+                domain: "legacy.com".into(),
+                error_kind: Some("network".into()),
+                suppressed: true,
+                ..Default::default()
+            },
+            Status {
+                domain: "down.com".into(),
+                error_kind: Some("network".into()),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(
+            summarize(&statuses, &[]),
+            Summary {
+                total: 5,
+                valid: 2,
+                warning: 2,
+                critical: 1,
+            }
+        );
+        assert_eq!(
+            summarize(&statuses, &["network".into()]),
+            Summary {
+                total: 5,
+                valid: 2,
+                warning: 3,
+                critical: 0,
+            }
+        );
+        assert_eq!(summarize(&[], &[]), Summary::default());
+    }
+
+    fn status(days_remaining: Option<i64>) -> Status {
+        Status {
+            domain: "example.com".into(),
+            valid: true,
+            days_remaining,
+            ..Default::default()
+        }
+    }
+
+    fn failed_status(domain: &str) -> Status {
+        Status {
+            domain: domain.into(),
+            valid: false,
+            error: "certificate expires in 3 days".into(),
+            error_kind: Some("expired".into()),
+            days_remaining: Some(3),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_domain_list() {
+        assert_eq!(
+            parse_domain_list(" Legacy.example.com,,old.example.com "),
+            vec!["legacy.example.com", "old.example.com"]
+        );
+        assert!(parse_domain_list("").is_empty());
+        assert_eq!(
+            parse_list(" Let's Encrypt, ,DigiCert Inc"),
+            vec!["Let's Encrypt", "DigiCert Inc"]
+        );
+    }
+
+    #[test]
+    fn test_suppress_failures() {
+        let policy = CheckPolicy {
+            suppressed_domains: parse_domain_list(
+                "legacy.example.com,legacy2.example.com,legacy3.example.com,healthy.example.com",
+            ),
+            ..Default::default()
+        };
+        let mut statuses = Vec::new();
+        for (line, valid) in [
+            ("legacy.example.com", false),
+            ("LEGACY2.example.com", false),
+            // Hosts are matched rather than display names
+            (r#""Legacy" = legacy3.example.com:8443"#, false),
+            (r#""legacy.example.com" = prod.example.com"#, false),
+            ("prod.example.com", false),
+            ("healthy.example.com", true),
+        ] {
+            let domain = ConfigDomain::new(line.into());
+            let mut status = Status {
+                domain: domain.display_name(),
+                valid,
+                ..failed_status("")
+            };
+            policy.suppress(&domain, &mut status);
+            statuses.push(status);
+        }
+        assert_eq!(
+            statuses
+                .iter()
+                .map(|status| status.suppressed)
+                .collect::<Vec<_>>(),
+            vec![true, true, true, false, false, false]
+        );
+        // Real state is kept
+        assert!(!statuses[0].valid);
+        assert_eq!(statuses[0].error_kind.as_deref(), Some("expired"));
+    }
+
+    #[test]
+    fn test_histogram() {
+        let statuses = vec![
+            status(Some(-3)),
+            status(Some(6)),
+            status(Some(7)),
+            status(Some(29)),
+            status(Some(30)),
+            status(Some(90)),
+            status(Some(400)),
+            status(None),
+        ];
+        assert_eq!(
+            histogram(&statuses),
+            vec![
+                ("<7d".to_string(), 2),
+                ("7-30d".to_string(), 2),
+                ("30-90d".to_string(), 1),
+                (">90d".to_string(), 2),
+            ]
+        );
+    }
+
+    fn get_object_error(code: &str) -> SdkError<GetObjectError, HttpResponse> {
+        get_object_error_with_status(code, 403)
+    }
+
+    fn get_object_error_with_status(
+        code: &str,
+        status: u16,
+    ) -> SdkError<GetObjectError, HttpResponse> {
+        SdkError::service_error(
+            GetObjectError::generic(
+                aws_sdk_s3::error::ErrorMetadata::builder()
+                    .code(code)
+                    .build(),
+            ),
+            HttpResponse::new(
+                status.try_into().unwrap(),
+                aws_sdk_s3::primitives::SdkBody::empty(),
+            ),
+        )
+    }
+
+    #[test]
+    fn test_map_get_object_error_access_denied() {
+        let err = map_get_object_error(
+            get_object_error("AccessDenied"),
+            "s3://bucket/config.txt",
+            Some("key-id"),
+        );
+        let err = err
+            .downcast::<MonitorError>()
+            .expect("should be a monitor error");
+        assert!(matches!(*err, MonitorError::Config(_)));
+        assert_eq!(
+            err.to_string(),
+            "config error: Access denied to S3 config s3://bucket/config.txt (KMS key key-id)"
+        );
+    }
+
+    #[test]
+    fn test_map_get_object_error_missing() {
+        let err = map_get_object_error(
+            get_object_error_with_status("NoSuchKey", 404),
+            "s3://bucket/domains-prod.txt",
+            None,
+        );
+        let err = err
+            .downcast::<MonitorError>()
+            .expect("should be a monitor error");
+        assert_eq!(
+            err.to_string(),
+            "config error: S3 config s3://bucket/domains-prod.txt does not exist"
+        );
+    }
+
+    #[test]
+    fn test_map_get_object_error_other() {
+        let err = map_get_object_error(
+            get_object_error_with_status("InternalError", 500),
+            "s3://bucket/config.txt",
+            Some("key-id"),
+        );
+        assert!(err.downcast::<MonitorError>().is_err());
+    }
+
+    #[test]
+    fn test_recheck_interval() {
+        assert_eq!(recheck_interval(None), chrono::Duration::days(1));
+        assert_eq!(recheck_interval(Some(400)), chrono::Duration::days(1));
+        assert_eq!(recheck_interval(Some(31)), chrono::Duration::days(1));
+        assert_eq!(recheck_interval(Some(30)), chrono::Duration::hours(6));
+        assert_eq!(recheck_interval(Some(7)), chrono::Duration::hours(6));
+        assert_eq!(recheck_interval(Some(6)), chrono::Duration::hours(1));
+        assert_eq!(recheck_interval(Some(-2)), chrono::Duration::hours(1));
+    }
+
+    #[test]
+    fn test_recommended_recheck_at() {
+        let now: DateTime<Utc> = "2024-05-01T00:00:00Z".parse().unwrap();
+        let status = |days_remaining| Status {
+            days_remaining,
+            ..Default::default()
+        };
+        assert_eq!(
+            recommended_recheck_at(&[], now),
+            now + chrono::Duration::days(1)
+        );
+        assert_eq!(
+            recommended_recheck_at(&[status(Some(90)), status(None), status(Some(12))], now),
+            now + chrono::Duration::hours(6)
+        );
+        assert_eq!(
+            recommended_recheck_at(&[status(Some(90)), status(Some(3))], now),
+            now + chrono::Duration::hours(1)
+        );
+    }
+
+    #[test]
+    fn test_s3_retry_config() {
+        let retry_config = s3_retry_config();
+        assert_eq!(retry_config.max_attempts(), S3_FETCH_ATTEMPTS);
+        assert_eq!(retry_config.initial_backoff(), S3_BACKOFF_BASE);
+    }
+
+    #[test]
+    fn test_check_encryption() {
+        let arn = "arn:aws:kms:us-east-1:123456789012:key/key-id";
+        assert!(check_encryption(None, None, None).is_ok());
+        assert!(check_encryption(
+            Some("key-id"),
+            Some(&ServerSideEncryption::AwsKms),
+            Some(arn)
+        )
+        .is_ok());
+        assert!(
+            check_encryption(Some(arn), Some(&ServerSideEncryption::AwsKms), Some(arn)).is_ok()
+        );
+        assert!(matches!(
+            check_encryption(
+                Some("other-key"),
+                Some(&ServerSideEncryption::AwsKms),
+                Some(arn)
+            ),
+            Err(MonitorError::Config(_))
+        ));
+        assert!(matches!(
+            check_encryption(Some("key-id"), Some(&ServerSideEncryption::Aes256), None),
+            Err(MonitorError::Config(_))
+        ));
+        // Only whole key ids match
+        for expected in ["id", "-id", "key/key-id", "123456789012:key/key-id"] {
+            assert!(
+                check_encryption(
+                    Some(expected),
+                    Some(&ServerSideEncryption::AwsKms),
+                    Some(arn)
+                )
+                .is_err(),
+                "{}",
+                expected
+            );
+        }
+    }
+
+    /// AWS config answering every request with a config object encrypted by `key_id`
+    async fn s3_config_with_object(key_id: &'static str) -> aws_config::SdkConfig {
+        use aws_sdk_s3::config::{Credentials, SharedCredentialsProvider};
+        use aws_sdk_s3::primitives::SdkBody;
+        use aws_smithy_runtime_api::client::http::{
+            http_client_fn, HttpConnector, HttpConnectorFuture, SharedHttpConnector,
+        };
+        use aws_smithy_runtime_api::client::orchestrator::HttpRequest;
+
+        #[derive(Debug)]
+        struct ObjectConnector(&'static str);
+
+        impl HttpConnector for ObjectConnector {
+            fn call(&self, _request: HttpRequest) -> HttpConnectorFuture {
+                let mut response =
+                    HttpResponse::new(200.try_into().unwrap(), SdkBody::from("a.com\nb.com\n"));
+                let headers = response.headers_mut();
+                headers.insert("x-amz-server-side-encryption", "aws:kms");
+                headers.insert("x-amz-server-side-encryption-aws-kms-key-id", self.0);
+                HttpConnectorFuture::ready(Ok(response))
+            }
+        }
+
+        aws_config::defaults(aws_config::BehaviorVersion::latest())
+            .region("us-east-1")
+            .credentials_provider(SharedCredentialsProvider::new(Credentials::new(
+                "key", "secret", None, None, "test",
+            )))
+            .http_client(http_client_fn(move |_, _| {
+                SharedHttpConnector::new(ObjectConnector(key_id))
+            }))
+            .load()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_parse_domains_encryption() {
+        let location = "s3://bucket/domains.txt";
+        let config = s3_config_with_object("arn:aws:kms:us-east-1:123456789012:key/key-id").await;
+        assert_eq!(
+            parse_domains(&config, location, Some("key-id"))
+                .await
+                .unwrap(),
+            vec!["a.com", "b.com"]
+        );
+        assert_eq!(
+            parse_domains(&config, location, None).await.unwrap(),
+            vec!["a.com", "b.com"]
+        );
+        assert!(parse_domains(&config, location, Some("other-key-id"))
+            .await
+            .is_err());
+        let config =
+            s3_config_with_object("arn:aws:kms:us-east-1:123456789012:key/other-key-id").await;
+        assert!(parse_domains(&config, location, Some("key-id"))
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_concurrency() {
+        assert_eq!(parse_concurrency(None), DEFAULT_CONCURRENCY);
+        assert_eq!(parse_concurrency(Some("0")), 1);
+        assert_eq!(parse_concurrency(Some("4")), 4);
+        assert_eq!(parse_concurrency(Some("100000")), MAX_CONCURRENCY);
+        assert_eq!(parse_concurrency(Some("many")), DEFAULT_CONCURRENCY);
+        assert_eq!(parse_concurrency(Some("-1")), DEFAULT_CONCURRENCY);
+    }
+
+    #[tokio::test]
+    async fn test_deliver_failing_sink() {
+        let mut sinks = sink::Sinks::default();
+        sinks.spawn("history", async { Ok(()) });
+        sinks.spawn("metrics", async {
+            Err(MonitorError::Network(std::io::ErrorKind::ConnectionRefused.into()).into())
+        });
+        let resp = Response {
+            req_id: "42".into(),
+            statuses: vec![Status {
+                domain: "example.com".into(),
+                valid: true,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let resp = deliver(sinks, Duration::from_secs(5), resp).await;
+        assert_eq!(resp.req_id, "42");
+        assert!(resp.statuses[0].valid);
+        assert_eq!(resp.sink_errors.len(), 1);
+        assert!(
+            resp.sink_errors[0].starts_with("metrics: network error"),
+            "{:?}",
+            resp.sink_errors
+        );
+    }
+
+    #[test]
+    fn test_report_keys() {
+        let checked_at = DateTime::parse_from_rfc3339("2024-05-01T10:02:03Z")
+            .unwrap()
+            .to_utc();
+        assert_eq!(
+            report_keys("reports", "archive/", "latest.json", checked_at),
+            (
+                "reports/archive/2024-05-01T100203Z.json".to_string(),
+                "reports/latest.json".to_string()
+            )
+        );
+        assert_eq!(
+            report_keys("team/reports/", "runs/", "current.json", checked_at),
+            (
+                "team/reports/runs/2024-05-01T100203Z.json".to_string(),
+                "team/reports/current.json".to_string()
+            )
+        );
+        assert_eq!(
+            report_keys("", "", "latest.json", checked_at),
+            (
+                "2024-05-01T100203Z.json".to_string(),
+                "latest.json".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_histogram_empty() {
+        let buckets = histogram(&[]);
+        assert_eq!(buckets.len(), 4);
+        assert!(buckets.iter().all(|(_, count)| *count == 0));
+    }
+}
@@ -0,0 +1,300 @@
+use crate::Status;
+use lambda_runtime::Error;
+use ssl_cert_monitor_common::Severity;
+use std::fmt::Write;
+
+/// Escape a label value for the Prometheus text exposition format
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Render statuses as metrics in the Prometheus text exposition format
+pub fn render_prometheus(statuses: &[Status]) -> String {
+    let mut out = String::new();
+
+    out.push_str("# HELP ssl_cert_expiry_days Days remaining until certificate expiration\n");
+    out.push_str("# TYPE ssl_cert_expiry_days gauge\n");
+    for status in statuses {
+        if let Some(days) = status.days_remaining {
+            let _ = writeln!(
+                out,
+                "ssl_cert_expiry_days{{domain=\"{}\"}} {}",
+                escape_label(&status.domain),
+                days
+            );
+        }
+    }
+
+    out.push_str("# HELP ssl_cert_valid Whether the certificate passed validation\n");
+    out.push_str("# TYPE ssl_cert_valid gauge\n");
+    for status in statuses {
+        let _ = writeln!(
+            out,
+            "ssl_cert_valid{{domain=\"{}\"}} {}",
+            escape_label(&status.domain),
+            u8::from(status.valid)
+        );
+    }
+
+    out
+}
+
+/// Quote a CSV field containing separators, quotes or line breaks (RFC 4180)
+fn escape_csv(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Render statuses as CSV with a header row, e.g. for spreadsheets.
+/// Failures of `warning_kinds` have a warning severity.
+pub fn render_csv(statuses: &[Status], warning_kinds: &[String]) -> String {
+    let mut out = String::from("domain,valid,severity,days_remaining,not_after,issuer,error\r\n");
+    for status in statuses {
+        let fields = [
+            escape_csv(&status.domain),
+            status.valid.to_string(),
+            Severity::of(status, warning_kinds).to_string(),
+            status
+                .days_remaining
+                .map(|days| days.to_string())
+                .unwrap_or_default(),
+            status
+                .not_after
+                .map(|date| date.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+                .unwrap_or_default(),
+            escape_csv(status.issuer.as_deref().unwrap_or_default()),
+            escape_csv(&status.error),
+        ];
+        out.push_str(&fields.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+/// Render statuses as a JSON array
+pub fn render_json(statuses: &[Status]) -> Result<String, Error> {
+    Ok(serde_json::to_string_pretty(statuses)?)
+}
+
+/// Render statuses as a human-readable table
+pub fn render_text(statuses: &[Status]) -> String {
+    let width = statuses
+        .iter()
+        .map(|status| status.domain.len())
+        .max()
+        .unwrap_or(0)
+        .max("DOMAIN".len());
+    let mut out = format!(
+        "{:<width$}  {:<7}  {:>5}  ERROR\n",
+        "DOMAIN", "STATUS", "DAYS"
+    );
+    for status in statuses {
+        let state = match (status.valid, status.suppressed) {
+            (true, _) => "valid",
+            (false, true) => "ignored",
+            (false, false) => "invalid",
+        };
+        let days = status
+            .days_remaining
+            .map(|days| days.to_string())
+            .unwrap_or_else(|| "-".into());
+        let _ = writeln!(
+            out,
+            "{:<width$}  {:<7}  {:>5}  {}",
+            status.domain, state, days, status.error
+        );
+    }
+    out
+}
+
+/// Escape text for HTML element content
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Render statuses as a standalone HTML page
+pub fn render_html(statuses: &[Status]) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>SSL certificates</title></head>\n<body>\n<table>\n<tr><th>Domain</th><th>Status</th><th>Days</th><th>Error</th></tr>\n",
+    );
+    for status in statuses {
+        let state = match (status.valid, status.suppressed) {
+            (true, _) => "valid",
+            (false, true) => "ignored",
+            (false, false) => "invalid",
+        };
+        let days = status
+            .days_remaining
+            .map(|days| days.to_string())
+            .unwrap_or_else(|| "-".into());
+        let _ = writeln!(
+            out,
+            "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            state,
+            escape_html(&status.domain),
+            state,
+            days,
+            escape_html(&status.error)
+        );
+    }
+    out.push_str("</table>\n</body>\n</html>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_json() {
+        let statuses = vec![Status {
+            domain: "example.com".into(),
+            valid: false,
+            error: "certificate expires in 3 days".into(),
+            error_kind: Some("expired".into()),
+            days_remaining: Some(3),
+            ..Default::default()
+        }];
+        let json: serde_json::Value =
+            serde_json::from_str(&render_json(&statuses).unwrap()).unwrap();
+        let status = &json.as_array().expect("should be an array")[0];
+        assert_eq!(status["domain"], "example.com");
+        assert_eq!(status["valid"], false);
+        assert_eq!(status["error"], "certificate expires in 3 days");
+        assert_eq!(status["error_kind"], "expired");
+        assert_eq!(status["days_remaining"], 3);
+        assert_eq!(status["not_after"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_render_text() {
+        let statuses = vec![
+            Status {
+                domain: "example.com".into(),
+                valid: true,
+                days_remaining: Some(42),
+                ..Default::default()
+            },
+            Status {
+                domain: "a.com".into(),
+                valid: false,
+                error: "network error".into(),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(
+            render_text(&statuses),
+            "DOMAIN       STATUS    DAYS  ERROR\n\
+             example.com  valid       42  \n\
+             a.com        invalid      -  network error\n"
+        );
+    }
+
+    #[test]
+    fn test_render_csv() {
+        let statuses = vec![
+            Status {
+                domain: "example.com".into(),
+                valid: true,
+                days_remaining: Some(42),
+                not_after: Some("2024-06-12T00:00:00Z".parse().unwrap()),
+                issuer: Some("Example CA, Inc.".into()),
+                ..Default::default()
+            },
+            Status {
+                domain: "a.com".into(),
+                valid: false,
+                error: "certificate error: Issuer \"Rogue\" is not allowed".into(),
+                error_kind: Some("certificate".into()),
+                ..Default::default()
+            },
+            Status {
+                domain: "b.com".into(),
+                valid: false,
+                error: "certificate expires in 3 days".into(),
+                error_kind: Some("expired".into()),
+                days_remaining: Some(3),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(
+            render_csv(&statuses, &[]),
+            "domain,valid,severity,days_remaining,not_after,issuer,error\r\n\
+             example.com,true,ok,42,2024-06-12T00:00:00Z,\"Example CA, Inc.\",\r\n\
+             a.com,false,critical,,,,\"certificate error: Issuer \"\"Rogue\"\" is not allowed\"\r\n\
+             b.com,false,warning,3,,,certificate expires in 3 days\r\n"
+        );
+        assert_eq!(escape_csv("line\nbreak"), "\"line\nbreak\"");
+        assert_eq!(escape_csv("plain"), "plain");
+    }
+
+    #[test]
+    fn test_render_html() {
+        let statuses = vec![
+            Status {
+                domain: "example.com".into(),
+                valid: true,
+                days_remaining: Some(42),
+                ..Default::default()
+            },
+            Status {
+                domain: "a.com".into(),
+                valid: false,
+                error: "name <a.com> mismatch".into(),
+                ..Default::default()
+            },
+        ];
+        let html = render_html(&statuses);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains(
+            "<tr class=\"valid\"><td>example.com</td><td>valid</td><td>42</td><td></td></tr>"
+        ));
+        assert!(html.contains(
+            "<tr class=\"invalid\"><td>a.com</td><td>invalid</td><td>-</td><td>name &lt;a.com&gt; mismatch</td></tr>"
+        ));
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label("example.com"), "example.com");
+        assert_eq!(escape_label("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+
+    #[test]
+    fn test_render_prometheus() {
+        let statuses = vec![
+            Status {
+                domain: "example.com".into(),
+                valid: true,
+                days_remaining: Some(12),
+                ..Default::default()
+            },
+            Status {
+                domain: "we\"ird.example".into(),
+                valid: false,
+                error: "network error".into(),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(
+            render_prometheus(&statuses),
+            "# HELP ssl_cert_expiry_days Days remaining until certificate expiration\n\
+             # TYPE ssl_cert_expiry_days gauge\n\
+             ssl_cert_expiry_days{domain=\"example.com\"} 12\n\
+             # HELP ssl_cert_valid Whether the certificate passed validation\n\
+             # TYPE ssl_cert_valid gauge\n\
+             ssl_cert_valid{domain=\"example.com\"} 1\n\
+             ssl_cert_valid{domain=\"we\\\"ird.example\"} 0\n"
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use crate::error::MonitorError;
+use quinn::crypto::rustls::{HandshakeData, QuicClientConfig};
+use rustls::pki_types::CertificateDer;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// ALPN protocol of HTTP/3
+const ALPN_H3: &[u8] = b"h3";
+
+/// Certificates and ALPN protocol retrieved during a QUIC handshake
+pub struct QuicHandshake {
+    pub certificates: Option<Vec<CertificateDer<'static>>>,
+    pub alpn_protocol: Option<String>,
+}
+
+fn network_error(err: impl std::fmt::Display) -> MonitorError {
+    MonitorError::Network(std::io::Error::other(err.to_string()))
+}
+
+/// TLS alerts sent when the presented certificate is rejected: bad, unsupported,
+/// revoked, expired, unknown certificate and unknown CA
+const CERTIFICATE_ALERTS: [u8; 6] = [42, 43, 44, 45, 46, 48];
+
+/// Classify a failed QUIC connection, telling TLS failures carried in
+/// crypto error codes from network failures
+fn connection_error(err: quinn::ConnectionError) -> MonitorError {
+    let quinn::ConnectionError::TransportError(err) = err else {
+        return network_error(err);
+    };
+    match u64::from(err.code) {
+        code if (0x100..0x200).contains(&code) => {
+            if CERTIFICATE_ALERTS.contains(&((code & 0xff) as u8)) {
+                MonitorError::Certificate(err.to_string())
+            } else {
+                MonitorError::Tls(rustls::Error::General(err.to_string()))
+            }
+        }
+        _ => network_error(err),
+    }
+}
+
+/// Local address of the endpoint reaching `addr`, from `bind_address` if set
+fn local_addr(addr: SocketAddr, bind_address: Option<IpAddr>) -> std::io::Result<SocketAddr> {
+    let ip = match (addr, bind_address) {
+        (_, Some(bind_address)) if addr.is_ipv4() != bind_address.is_ipv4() => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("cannot reach {} from {}", addr, bind_address),
+            ));
+        }
+        (_, Some(bind_address)) => bind_address,
+        (SocketAddr::V4(_), None) => Ipv4Addr::UNSPECIFIED.into(),
+        (SocketAddr::V6(_), None) => Ipv6Addr::UNSPECIFIED.into(),
+    };
+    Ok(SocketAddr::new(ip, 0))
+}
+
+/// Perform a QUIC handshake with `addr` and return the presented certificates.
+/// Blocks on the current Tokio runtime, so it must be called from a blocking task.
+pub fn handshake(
+    mut config: rustls::ClientConfig,
+    domain: &str,
+    addr: SocketAddr,
+    bind_address: Option<IpAddr>,
+    timeout: Duration,
+) -> Result<QuicHandshake, MonitorError> {
+    let runtime = tokio::runtime::Handle::try_current()
+        .map_err(|_| MonitorError::Config("QUIC requires a Tokio runtime".into()))?;
+    config.alpn_protocols = vec![ALPN_H3.to_vec()];
+    let crypto = QuicClientConfig::try_from(config)
+        .map_err(|err| MonitorError::Config(format!("Cannot configure QUIC: {}", err)))?;
+    let client_config = quinn::ClientConfig::new(Arc::new(crypto));
+
+    runtime.block_on(async {
+        let bind_addr = local_addr(addr, bind_address).map_err(MonitorError::Network)?;
+        let mut endpoint = quinn::Endpoint::client(bind_addr).map_err(MonitorError::Network)?;
+        endpoint.set_default_client_config(client_config);
+
+        let connecting = endpoint.connect(addr, domain).map_err(network_error)?;
+        let connection = tokio::time::timeout(timeout, connecting)
+            .await
+            .map_err(|_| {
+                MonitorError::Network(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("QUIC handshake with {} timed out", addr),
+                ))
+            })?
+            .map_err(connection_error)?;
+
+        let certificates = connection
+            .peer_identity()
+            .and_then(|identity| identity.downcast::<Vec<CertificateDer<'static>>>().ok())
+            .map(|certificates| *certificates);
+        let alpn_protocol = connection
+            .handshake_data()
+            .and_then(|data| data.downcast::<HandshakeData>().ok())
+            .and_then(|data| data.protocol)
+            .map(|protocol| String::from_utf8_lossy(&protocol).into_owned());
+
+        connection.close(0u32.into(), b"done");
+        endpoint.wait_idle().await;
+
+        Ok(QuicHandshake {
+            certificates,
+            alpn_protocol,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_addr() {
+        let v4: SocketAddr = "192.0.2.1:443".parse().unwrap();
+        let v6: SocketAddr = "[2001:db8::1]:443".parse().unwrap();
+        assert_eq!(local_addr(v4, None).unwrap(), "0.0.0.0:0".parse().unwrap());
+        assert_eq!(local_addr(v6, None).unwrap(), "[::]:0".parse().unwrap());
+        assert_eq!(
+            local_addr(v4, Some("10.0.0.5".parse().unwrap())).unwrap(),
+            "10.0.0.5:0".parse().unwrap()
+        );
+        let err = local_addr(v4, Some("::1".parse().unwrap())).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_connection_error() {
+        let transport = |code: quinn::TransportErrorCode| {
+            connection_error(quinn::ConnectionError::TransportError(code.into()))
+        };
+        // Unknown CA
+        let err = transport(quinn::TransportErrorCode::crypto(48));
+        assert_eq!(err.code(), "CERT_INVALID");
+        // Handshake failure
+        let err = transport(quinn::TransportErrorCode::crypto(40));
+        assert_eq!(err.code(), "TLS");
+        let err = transport(quinn::TransportErrorCode::PROTOCOL_VIOLATION);
+        assert_eq!(err.code(), "NET");
+        let err = connection_error(quinn::ConnectionError::TimedOut);
+        assert_eq!(err.code(), "NET");
+    }
+}
@@ -0,0 +1,163 @@
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Source of time for rate limiting
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration);
+}
+
+/// Real time blocking the current thread
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration)
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Lowest rate, one connection in 100 seconds, keeping waits within a Duration
+pub const MIN_RATE: f64 = 0.01;
+
+/// Token bucket limiting the rate of new connections across all domains
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
+    clock: Box<dyn Clock>,
+}
+
+impl RateLimiter {
+    /// Allow `rate` connections per second, with bursts of up to one second
+    pub fn new(rate: f64) -> Self {
+        Self::with_clock(rate, Box::new(SystemClock))
+    }
+
+    pub fn with_clock(rate: f64, clock: Box<dyn Clock>) -> Self {
+        let rate = rate.max(MIN_RATE);
+        let burst = rate.max(1.0);
+        Self {
+            rate,
+            burst,
+            bucket: Mutex::new(Bucket {
+                tokens: burst,
+                updated: clock.now(),
+            }),
+            clock,
+        }
+    }
+
+    /// Block until a connection is allowed
+    pub fn acquire(&self) {
+        loop {
+            let wait = {
+                // Bucket is updated at once, so it is consistent even if poisoned
+                let mut bucket = self.bucket.lock().unwrap_or_else(PoisonError::into_inner);
+                let now = self.clock.now();
+                let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+                bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+                bucket.updated = now;
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate)
+            };
+            self.clock.sleep(wait);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// Clock advancing only when sleeping
+    struct MockClock {
+        now: Mutex<Instant>,
+        start: Instant,
+    }
+
+    impl MockClock {
+        fn elapsed(&self) -> Duration {
+            self.now.lock().unwrap().duration_since(self.start)
+        }
+    }
+
+    impl Clock for Arc<MockClock> {
+        fn now(&self) -> Instant {
+            *self.now.lock().unwrap()
+        }
+
+        fn sleep(&self, duration: Duration) {
+            *self.now.lock().unwrap() += duration;
+        }
+    }
+
+    fn mock_clock() -> Arc<MockClock> {
+        let start = Instant::now();
+        Arc::new(MockClock {
+            now: Mutex::new(start),
+            start,
+        })
+    }
+
+    #[test]
+    fn test_burst_then_rate() {
+        let clock = mock_clock();
+        let limiter = RateLimiter::with_clock(2.0, Box::new(clock.clone()));
+
+        limiter.acquire();
+        limiter.acquire();
+        assert_eq!(clock.elapsed(), Duration::ZERO);
+
+        limiter.acquire();
+        assert_eq!(clock.elapsed(), Duration::from_millis(500));
+        limiter.acquire();
+        assert_eq!(clock.elapsed(), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn test_tiny_rate() {
+        let clock = mock_clock();
+        let limiter = RateLimiter::with_clock(1e-300, Box::new(clock.clone()));
+
+        limiter.acquire();
+        limiter.acquire();
+        assert_eq!(clock.elapsed(), Duration::from_secs(100));
+    }
+
+    #[test]
+    fn test_refill_while_idle() {
+        let clock = mock_clock();
+        let limiter = RateLimiter::with_clock(1.0, Box::new(clock.clone()));
+
+        limiter.acquire();
+        clock.sleep(Duration::from_secs(10));
+        // Idle time does not accumulate beyond the burst
+        limiter.acquire();
+        assert_eq!(clock.elapsed(), Duration::from_secs(10));
+        limiter.acquire();
+        assert_eq!(clock.elapsed(), Duration::from_secs(11));
+    }
+
+    #[test]
+    fn test_slow_rate() {
+        let clock = mock_clock();
+        let limiter = RateLimiter::with_clock(0.5, Box::new(clock.clone()));
+
+        limiter.acquire();
+        limiter.acquire();
+        assert_eq!(clock.elapsed(), Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,224 @@
+use crate::dane::TlsaRecord;
+use crate::error::MonitorError;
+use hickory_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use hickory_resolver::error::ResolveErrorKind;
+use hickory_resolver::proto::rr::{RData, RecordType};
+use hickory_resolver::TokioAsyncResolver;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use url::Url;
+
+/// Resolves host names to socket addresses for connections
+pub trait Resolver: Send + Sync {
+    fn resolve(&self, host: &str, port: u16) -> std::io::Result<Vec<SocketAddr>>;
+
+    /// TLSA records of `name`, empty if there are none
+    fn tlsa(&self, name: &str) -> std::io::Result<Vec<TlsaRecord>> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!(
+                "cannot look up TLSA records of {} without DNS_RESOLVER",
+                name
+            ),
+        ))
+    }
+}
+
+/// Resolver backed by the system name resolution
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve(&self, host: &str, port: u16) -> std::io::Result<Vec<SocketAddr>> {
+        Ok((host, port).to_socket_addrs()?.collect())
+    }
+}
+
+/// Resolver querying a specific nameserver.
+/// Lookups are driven by the runtime it was created in, so it must be
+/// used from blocking tasks and not from async code.
+pub struct NameserverResolver {
+    resolver: TokioAsyncResolver,
+    /// Resolver of TLSA records, validating DNSSEC if requested
+    tlsa_resolver: TokioAsyncResolver,
+    runtime: tokio::runtime::Handle,
+}
+
+impl Resolver for NameserverResolver {
+    fn resolve(&self, host: &str, port: u16) -> std::io::Result<Vec<SocketAddr>> {
+        let lookup = self.runtime.block_on(self.resolver.lookup_ip(host))?;
+        Ok(lookup.iter().map(|ip| SocketAddr::new(ip, port)).collect())
+    }
+
+    fn tlsa(&self, name: &str) -> std::io::Result<Vec<TlsaRecord>> {
+        match self
+            .runtime
+            .block_on(self.tlsa_resolver.lookup(name, RecordType::TLSA))
+        {
+            Ok(lookup) => Ok(lookup
+                .iter()
+                .filter_map(|rdata| match rdata {
+                    RData::TLSA(tlsa) => Some(tlsa.into()),
+                    _ => None,
+                })
+                .collect()),
+            Err(err) if matches!(err.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+                Ok(Vec::new())
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Source of name resolution
+#[derive(Debug, PartialEq)]
+pub enum ResolverKind {
+    System,
+    /// Plain DNS nameserver
+    Nameserver(SocketAddr),
+    /// DNS-over-HTTPS endpoint
+    DnsOverHttps(SocketAddr),
+}
+
+/// Parse a resolver specification: empty for system resolution,
+/// `IP[:port]` for a nameserver or `https://IP[:port]` for DNS-over-HTTPS
+pub fn parse_resolver_kind(value: Option<&str>) -> Result<ResolverKind, MonitorError> {
+    let value = match value.map(str::trim) {
+        None | Some("") => return Ok(ResolverKind::System),
+        Some(value) => value,
+    };
+    let wrong_resolver = || MonitorError::Config(format!("Wrong DNS resolver {}", value));
+
+    if value.starts_with("https://") {
+        let url = Url::parse(value).map_err(|_| wrong_resolver())?;
+        let ip = match url.host() {
+            Some(url::Host::Ipv4(ip)) => IpAddr::V4(ip),
+            Some(url::Host::Ipv6(ip)) => IpAddr::V6(ip),
+            _ => return Err(wrong_resolver()),
+        };
+        let port = url.port().unwrap_or(443);
+        return Ok(ResolverKind::DnsOverHttps(SocketAddr::new(ip, port)));
+    }
+
+    value
+        .parse::<SocketAddr>()
+        .or_else(|_| value.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 53)))
+        .map(ResolverKind::Nameserver)
+        .map_err(|_| wrong_resolver())
+}
+
+/// Build a resolver, must be called within a Tokio runtime.
+/// With `dnssec`, TLSA answers of nameservers are validated with DNSSEC,
+/// which the system resolver cannot do. Addresses are resolved without it,
+/// so hosts in unsigned zones stay reachable.
+pub fn build_resolver(
+    kind: &ResolverKind,
+    dnssec: bool,
+) -> Result<Box<dyn Resolver>, MonitorError> {
+    let name_servers = match kind {
+        ResolverKind::System if dnssec => {
+            return Err(MonitorError::Config(
+                "DNSSEC validation needs DNS_RESOLVER".into(),
+            ))
+        }
+        ResolverKind::System => return Ok(Box::new(SystemResolver)),
+        ResolverKind::Nameserver(addr) => {
+            NameServerConfigGroup::from_ips_clear(&[addr.ip()], addr.port(), true)
+        }
+        ResolverKind::DnsOverHttps(addr) => NameServerConfigGroup::from_ips_https(
+            &[addr.ip()],
+            addr.port(),
+            addr.ip().to_string(),
+            true,
+        ),
+    };
+    let config = ResolverConfig::from_parts(None, vec![], name_servers);
+    let runtime = tokio::runtime::Handle::try_current()
+        .map_err(|err| MonitorError::Config(format!("Cannot create DNS resolver: {}", err)))?;
+    let resolver = TokioAsyncResolver::tokio(config.clone(), ResolverOpts::default());
+    let tlsa_resolver = if dnssec {
+        let mut opts = ResolverOpts::default();
+        opts.validate = true;
+        TokioAsyncResolver::tokio(config, opts)
+    } else {
+        resolver.clone()
+    };
+    Ok(Box::new(NameserverResolver {
+        resolver,
+        tlsa_resolver,
+        runtime,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_resolver_kind() {
+        assert_eq!(parse_resolver_kind(None).unwrap(), ResolverKind::System);
+        assert_eq!(
+            parse_resolver_kind(Some(" ")).unwrap(),
+            ResolverKind::System
+        );
+        assert_eq!(
+            parse_resolver_kind(Some("10.0.0.2")).unwrap(),
+            ResolverKind::Nameserver("10.0.0.2:53".parse().unwrap())
+        );
+        assert_eq!(
+            parse_resolver_kind(Some("10.0.0.2:5353")).unwrap(),
+            ResolverKind::Nameserver("10.0.0.2:5353".parse().unwrap())
+        );
+        assert_eq!(
+            parse_resolver_kind(Some("[2001:db8::1]:53")).unwrap(),
+            ResolverKind::Nameserver("[2001:db8::1]:53".parse().unwrap())
+        );
+        assert_eq!(
+            parse_resolver_kind(Some("https://1.1.1.1/dns-query")).unwrap(),
+            ResolverKind::DnsOverHttps("1.1.1.1:443".parse().unwrap())
+        );
+        assert_eq!(
+            parse_resolver_kind(Some("https://1.1.1.1:8443")).unwrap(),
+            ResolverKind::DnsOverHttps("1.1.1.1:8443".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_parse_resolver_kind_wrong() {
+        for value in ["dns.example", "https://cloudflare-dns.com", "10.0.0.2:port"] {
+            assert!(
+                matches!(
+                    parse_resolver_kind(Some(value)),
+                    Err(MonitorError::Config(_))
+                ),
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_resolver() {
+        for kind in [
+            ResolverKind::System,
+            ResolverKind::Nameserver("10.0.0.2:53".parse().unwrap()),
+            ResolverKind::DnsOverHttps("1.1.1.1:443".parse().unwrap()),
+        ] {
+            assert!(build_resolver(&kind, false).is_ok(), "{:?}", kind);
+        }
+        assert!(build_resolver(
+            &ResolverKind::Nameserver("10.0.0.2:53".parse().unwrap()),
+            true
+        )
+        .is_ok());
+        assert!(matches!(
+            build_resolver(&ResolverKind::System, true),
+            Err(MonitorError::Config(_))
+        ));
+        assert_eq!(
+            SystemResolver
+                .tlsa("_443._tcp.example.com")
+                .unwrap_err()
+                .kind(),
+            std::io::ErrorKind::Unsupported
+        );
+    }
+}
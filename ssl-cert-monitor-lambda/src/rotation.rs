@@ -0,0 +1,301 @@
+use crate::{dynamodb, Status};
+use aws_sdk_dynamodb::types::AttributeValue;
+use lambda_runtime::tracing::{info, warn};
+use lambda_runtime::Error;
+use std::collections::HashMap;
+
+/// Renewed certificates valid for less than this fraction of the previous
+/// validity period are flagged
+const SHORTENED_VALIDITY_RATIO: f64 = 0.5;
+
+/// Leaf certificate last seen for a domain
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeenCertificate {
+    pub fingerprint: String,
+    pub validity_days: Option<i64>,
+}
+
+/// Storage for the last seen leaf certificate of each domain, read and
+/// written for all domains of a run at once
+pub trait FingerprintStore {
+    async fn get_many(
+        &self,
+        table: &str,
+        domains: &[String],
+    ) -> Result<HashMap<String, SeenCertificate>, Error>;
+    async fn put_many(
+        &self,
+        table: &str,
+        seen: Vec<(String, SeenCertificate)>,
+    ) -> Result<(), Error>;
+}
+
+impl FingerprintStore for aws_sdk_dynamodb::Client {
+    async fn get_many(
+        &self,
+        table: &str,
+        domains: &[String],
+    ) -> Result<HashMap<String, SeenCertificate>, Error> {
+        let items = dynamodb::batch_get(self, table, domains).await?;
+        Ok(items
+            .into_iter()
+            .filter_map(|item| {
+                let seen = SeenCertificate {
+                    fingerprint: item.get("fingerprint")?.as_s().ok()?.clone(),
+                    validity_days: item
+                        .get("validity_days")
+                        .and_then(|value| value.as_n().ok())
+                        .and_then(|value| value.parse().ok()),
+                };
+                Some((item.get("domain")?.as_s().ok()?.clone(), seen))
+            })
+            .collect())
+    }
+
+    async fn put_many(
+        &self,
+        table: &str,
+        seen: Vec<(String, SeenCertificate)>,
+    ) -> Result<(), Error> {
+        let items = seen
+            .into_iter()
+            .map(|(domain, seen)| {
+                let mut item = dynamodb::Item::from([
+                    ("domain".to_string(), AttributeValue::S(domain)),
+                    (
+                        "fingerprint".to_string(),
+                        AttributeValue::S(seen.fingerprint),
+                    ),
+                ]);
+                if let Some(validity_days) = seen.validity_days {
+                    item.insert(
+                        "validity_days".to_string(),
+                        AttributeValue::N(validity_days.to_string()),
+                    );
+                }
+                item
+            })
+            .collect();
+        dynamodb::batch_put(self, table, items).await
+    }
+}
+
+/// Whether the leaf certificate differs from the one seen before.
+/// A domain seen for the first time is not rotated.
+pub fn is_rotated(previous: Option<&str>, current: Option<&str>) -> bool {
+    matches!((previous, current), (Some(previous), Some(current)) if previous != current)
+}
+
+/// Whether the renewed certificate is valid for much shorter than the previous
+/// one, e.g. issued by a short-lived internal profile by mistake
+pub fn is_validity_shortened(previous: Option<i64>, current: Option<i64>) -> bool {
+    match (previous, current) {
+        (Some(previous), Some(current)) if previous > 0 => {
+            (current as f64) < previous as f64 * SHORTENED_VALIDITY_RATIO
+        }
+        _ => false,
+    }
+}
+
+/// Flag domains whose certificate changed since the last check and remember
+/// current certificates. Rotations during maintenance are expected, while
+/// a sharply shortened validity period is flagged regardless.
+/// Certificates of all domains are read and written in batches.
+pub async fn detect_rotations<S: FingerprintStore>(
+    store: &S,
+    table: &str,
+    statuses: &mut [Status],
+    maintenance: bool,
+) -> Result<(), Error> {
+    let domains: Vec<String> = statuses
+        .iter()
+        .filter(|status| status.fingerprint.is_some())
+        .map(|status| status.domain.clone())
+        .collect();
+    if domains.is_empty() {
+        return Ok(());
+    }
+    let mut previous = store.get_many(table, &domains).await?;
+    let mut changed = Vec::new();
+    let mut rotated = 0;
+    for status in statuses.iter_mut() {
+        let Some(fingerprint) = status.fingerprint.clone() else {
+            continue;
+        };
+        let current = SeenCertificate {
+            fingerprint,
+            validity_days: status.validity_days,
+        };
+        let previous = previous.remove(&status.domain);
+        if previous.as_ref() == Some(&current) {
+            continue;
+        }
+        let previous_fingerprint = previous.as_ref().map(|seen| seen.fingerprint.as_str());
+        if is_rotated(previous_fingerprint, Some(&current.fingerprint)) {
+            if !maintenance {
+                warn!("Certificate of {} rotated unexpectedly", status.domain);
+                status.rotated = true;
+                rotated += 1;
+            }
+            let previous_validity = previous.and_then(|seen| seen.validity_days);
+            if is_validity_shortened(previous_validity, current.validity_days) {
+                warn!(
+                    "Certificate of {} renewed for {:?} days instead of {:?}",
+                    status.domain, current.validity_days, previous_validity
+                );
+                status.validity_shortened = true;
+            }
+        }
+        changed.push((status.domain.clone(), current));
+    }
+    info!("Detected {} unexpected certificate rotations", rotated);
+    if !changed.is_empty() {
+        store.put_many(table, changed).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_is_rotated() {
+        assert!(!is_rotated(None, None));
+        assert!(!is_rotated(None, Some("aa")));
+        assert!(!is_rotated(Some("aa"), None));
+        assert!(!is_rotated(Some("aa"), Some("aa")));
+        assert!(is_rotated(Some("aa"), Some("bb")));
+    }
+
+    #[test]
+    fn test_is_validity_shortened() {
+        assert!(!is_validity_shortened(None, Some(90)));
+        assert!(!is_validity_shortened(Some(90), None));
+        assert!(!is_validity_shortened(Some(0), Some(7)));
+        assert!(!is_validity_shortened(Some(397), Some(365)));
+        assert!(!is_validity_shortened(Some(90), Some(45)));
+        assert!(is_validity_shortened(Some(397), Some(7)));
+    }
+
+    #[derive(Default)]
+    struct MockStore {
+        fingerprints: Mutex<HashMap<String, SeenCertificate>>,
+    }
+
+    impl MockStore {
+        async fn seen(&self, domain: &str, fingerprint: &str, validity_days: Option<i64>) {
+            let seen = SeenCertificate {
+                fingerprint: fingerprint.into(),
+                validity_days,
+            };
+            self.put_many("certs", vec![(domain.to_string(), seen)])
+                .await
+                .expect("should store");
+        }
+
+        fn fingerprint(&self, domain: &str) -> Option<String> {
+            let fingerprints = self.fingerprints.lock().unwrap();
+            fingerprints
+                .get(domain)
+                .map(|seen| seen.fingerprint.clone())
+        }
+    }
+
+    impl FingerprintStore for MockStore {
+        async fn get_many(
+            &self,
+            _table: &str,
+            domains: &[String],
+        ) -> Result<HashMap<String, SeenCertificate>, Error> {
+            let fingerprints = self.fingerprints.lock().unwrap();
+            Ok(domains
+                .iter()
+                .filter_map(|domain| Some((domain.clone(), fingerprints.get(domain)?.clone())))
+                .collect())
+        }
+
+        async fn put_many(
+            &self,
+            _table: &str,
+            seen: Vec<(String, SeenCertificate)>,
+        ) -> Result<(), Error> {
+            self.fingerprints.lock().unwrap().extend(seen);
+            Ok(())
+        }
+    }
+
+    fn status(domain: &str, fingerprint: Option<&str>) -> Status {
+        Status {
+            domain: domain.into(),
+            fingerprint: fingerprint.map(String::from),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_detect_rotations() {
+        let store = MockStore::default();
+        store.seen("rotated.com", "aa", None).await;
+        store.seen("same.com", "bb", None).await;
+        let mut statuses = vec![
+            status("rotated.com", Some("cc")),
+            status("same.com", Some("bb")),
+            status("new.com", Some("dd")),
+            status("down.com", None),
+        ];
+        detect_rotations(&store, "certs", &mut statuses, false)
+            .await
+            .expect("should succeed");
+        let rotated: Vec<bool> = statuses.iter().map(|status| status.rotated).collect();
+        assert_eq!(rotated, vec![true, false, false, false]);
+        assert_eq!(store.fingerprint("rotated.com").as_deref(), Some("cc"));
+        assert_eq!(store.fingerprint("new.com").as_deref(), Some("dd"));
+        assert_eq!(store.fingerprint("down.com"), None);
+    }
+
+    #[tokio::test]
+    async fn test_detect_rotations_in_maintenance() {
+        let store = MockStore::default();
+        store.seen("rotated.com", "aa", None).await;
+        let mut statuses = vec![status("rotated.com", Some("cc"))];
+        detect_rotations(&store, "certs", &mut statuses, true)
+            .await
+            .expect("should succeed");
+        assert!(!statuses[0].rotated);
+        assert_eq!(store.fingerprint("rotated.com").as_deref(), Some("cc"));
+    }
+
+    #[tokio::test]
+    async fn test_detect_shortened_validity() {
+        let store = MockStore::default();
+        store.seen("shortened.com", "aa", Some(397)).await;
+        store.seen("renewed.com", "bb", Some(90)).await;
+        store.seen("same.com", "cc", Some(397)).await;
+        let mut statuses = vec![
+            Status {
+                validity_days: Some(7),
+                ..status("shortened.com", Some("dd"))
+            },
+            Status {
+                validity_days: Some(90),
+                ..status("renewed.com", Some("ee"))
+            },
+            Status {
+                validity_days: Some(397),
+                ..status("same.com", Some("cc"))
+            },
+        ];
+        detect_rotations(&store, "certs", &mut statuses, true)
+            .await
+            .expect("should succeed");
+        let shortened: Vec<bool> = statuses
+            .iter()
+            .map(|status| status.validity_shortened)
+            .collect();
+        assert_eq!(shortened, vec![true, false, false]);
+        let seen = store.fingerprints.lock().unwrap()["shortened.com"].clone();
+        assert_eq!(seen.validity_days, Some(7));
+    }
+}
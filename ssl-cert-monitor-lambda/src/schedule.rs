@@ -0,0 +1,293 @@
+use crate::{dynamodb, recheck_interval, Status};
+use aws_sdk_dynamodb::types::AttributeValue;
+use chrono::{DateTime, Utc};
+use lambda_runtime::tracing::{info, warn};
+use lambda_runtime::Error;
+use std::collections::HashMap;
+
+/// Last healthy check of a domain
+#[derive(Debug, Clone, PartialEq)]
+pub struct LastCheck {
+    pub checked_at: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+}
+
+impl LastCheck {
+    pub fn days_remaining(&self, now: DateTime<Utc>) -> i64 {
+        (self.not_after - now).num_days()
+    }
+}
+
+/// Storage for the last healthy check of each domain, read and written
+/// for all domains of a run at once
+pub trait ScheduleStore {
+    async fn get_many(
+        &self,
+        table: &str,
+        domains: &[String],
+    ) -> Result<HashMap<String, LastCheck>, Error>;
+    async fn put_many(&self, table: &str, checks: Vec<(String, LastCheck)>) -> Result<(), Error>;
+}
+
+impl ScheduleStore for aws_sdk_dynamodb::Client {
+    async fn get_many(
+        &self,
+        table: &str,
+        domains: &[String],
+    ) -> Result<HashMap<String, LastCheck>, Error> {
+        let items = dynamodb::batch_get(self, table, domains).await?;
+        let time = |item: &dynamodb::Item, name: &str| {
+            item.get(name)?.as_s().ok()?.parse::<DateTime<Utc>>().ok()
+        };
+        Ok(items
+            .iter()
+            .filter_map(|item| {
+                let last = LastCheck {
+                    checked_at: time(item, "checked_at")?,
+                    not_after: time(item, "not_after")?,
+                };
+                Some((item.get("domain")?.as_s().ok()?.clone(), last))
+            })
+            .collect())
+    }
+
+    async fn put_many(&self, table: &str, checks: Vec<(String, LastCheck)>) -> Result<(), Error> {
+        let items = checks
+            .into_iter()
+            .map(|(domain, last)| {
+                dynamodb::Item::from([
+                    ("domain".to_string(), AttributeValue::S(domain)),
+                    (
+                        "checked_at".to_string(),
+                        AttributeValue::S(last.checked_at.to_rfc3339()),
+                    ),
+                    (
+                        "not_after".to_string(),
+                        AttributeValue::S(last.not_after.to_rfc3339()),
+                    ),
+                ])
+            })
+            .collect();
+        dynamodb::batch_put(self, table, items).await
+    }
+}
+
+/// Whether a domain is due for a check: domains far from expiry are checked
+/// less often after a healthy check, while unknown domains and domains whose
+/// certificate got within `threshold_days` of expiry are always checked
+pub fn should_check(last: Option<&LastCheck>, now: DateTime<Utc>, threshold_days: u64) -> bool {
+    match last {
+        Some(last) if last.checked_at <= now => {
+            let threshold = chrono::Duration::days(threshold_days as i64);
+            last.not_after - now <= threshold
+                || now - last.checked_at >= recheck_interval(Some(last.days_remaining(now)))
+        }
+        _ => true,
+    }
+}
+
+/// Split domains into the ones due for a check and the ones recently
+/// confirmed healthy, using the expiration threshold of each domain.
+/// All domains are checked if last checks cannot be read.
+pub async fn skip_recent<S: ScheduleStore>(
+    store: &S,
+    table: &str,
+    domains: Vec<String>,
+    now: DateTime<Utc>,
+    threshold_days: impl Fn(&str) -> u64,
+) -> (Vec<String>, Vec<(String, LastCheck)>) {
+    let mut checks = match store.get_many(table, &domains).await {
+        Ok(checks) => checks,
+        Err(err) => {
+            warn!("Cannot read last checks: {}", err);
+            HashMap::new()
+        }
+    };
+    let mut due = Vec::with_capacity(domains.len());
+    let mut skipped = Vec::new();
+    for domain in domains {
+        match checks.remove(&domain) {
+            Some(last) if !should_check(Some(&last), now, threshold_days(&domain)) => {
+                skipped.push((domain, last))
+            }
+            _ => due.push(domain),
+        }
+    }
+    info!(
+        "Skipping {} recently checked domains, {} due",
+        skipped.len(),
+        due.len()
+    );
+    (due, skipped)
+}
+
+/// Remember healthy checks of domains, listed in the same order as statuses
+pub async fn remember<S: ScheduleStore>(
+    store: &S,
+    table: &str,
+    domains: &[String],
+    statuses: &[Status],
+    now: DateTime<Utc>,
+) -> Result<(), Error> {
+    let checks: Vec<_> = domains
+        .iter()
+        .zip(statuses)
+        .filter_map(|(domain, status)| {
+            let not_after = status.not_after.filter(|_| status.valid)?;
+            let last = LastCheck {
+                checked_at: now,
+                not_after,
+            };
+            Some((domain.clone(), last))
+        })
+        .collect();
+    if checks.is_empty() {
+        return Ok(());
+    }
+    store.put_many(table, checks).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn now() -> DateTime<Utc> {
+        "2024-05-01T12:00:00Z".parse().unwrap()
+    }
+
+    fn last_check(hours_ago: i64, days_remaining: i64) -> LastCheck {
+        LastCheck {
+            checked_at: now() - chrono::Duration::hours(hours_ago),
+            not_after: now() + chrono::Duration::days(days_remaining),
+        }
+    }
+
+    #[test]
+    fn test_should_check() {
+        assert!(should_check(None, now(), 10));
+        for (hours_ago, days_remaining, check) in [
+            // Far from expiry, checked daily
+            (1, 120, false),
+            (23, 120, false),
+            (24, 120, true),
+            // Within a month, checked every 6 hours
+            (5, 20, false),
+            (6, 20, true),
+            // Within a week, checked hourly
+            (0, 3, false),
+            (1, 3, true),
+            // Checked in the future, like with a skewed clock
+            (-2, 120, true),
+        ] {
+            assert_eq!(
+                should_check(Some(&last_check(hours_ago, days_remaining)), now(), 2),
+                check,
+                "{} hours ago, {} days remaining",
+                hours_ago,
+                days_remaining
+            );
+        }
+        // Certificates within the expiration threshold are checked every run
+        assert!(!should_check(Some(&last_check(2, 20)), now(), 10));
+        assert!(should_check(Some(&last_check(2, 20)), now(), 20));
+        assert!(should_check(Some(&last_check(0, 3)), now(), 10));
+    }
+
+    #[derive(Default)]
+    struct MockStore {
+        checks: Mutex<HashMap<String, LastCheck>>,
+        unreadable: bool,
+    }
+
+    impl ScheduleStore for MockStore {
+        async fn get_many(
+            &self,
+            _table: &str,
+            domains: &[String],
+        ) -> Result<HashMap<String, LastCheck>, Error> {
+            if self.unreadable {
+                return Err("throttled".into());
+            }
+            let checks = self.checks.lock().unwrap();
+            Ok(domains
+                .iter()
+                .filter_map(|domain| Some((domain.clone(), checks.get(domain)?.clone())))
+                .collect())
+        }
+
+        async fn put_many(
+            &self,
+            _table: &str,
+            checks: Vec<(String, LastCheck)>,
+        ) -> Result<(), Error> {
+            self.checks.lock().unwrap().extend(checks);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_skip_recent() {
+        let store = MockStore::default();
+        store
+            .put_many(
+                "checks",
+                vec![
+                    ("fresh.com".into(), last_check(2, 120)),
+                    ("due.com".into(), last_check(30, 120)),
+                    ("critical.com".into(), last_check(2, 40)),
+                ],
+            )
+            .await
+            .unwrap();
+        let domains = ["fresh.com", "due.com", "new.com", "critical.com"]
+            .map(String::from)
+            .to_vec();
+        let threshold = |domain: &str| if domain == "critical.com" { 45 } else { 10 };
+        let (due, skipped) = skip_recent(&store, "checks", domains.clone(), now(), threshold).await;
+        assert_eq!(due, vec!["due.com", "new.com", "critical.com"]);
+        assert_eq!(skipped, vec![("fresh.com".to_string(), last_check(2, 120))]);
+
+        // Domains are checked if last checks cannot be read
+        let store = MockStore {
+            unreadable: true,
+            ..Default::default()
+        };
+        let (due, skipped) = skip_recent(&store, "checks", domains.clone(), now(), threshold).await;
+        assert_eq!(due, domains);
+        assert!(skipped.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_remember() {
+        let store = MockStore::default();
+        let not_after = now() + chrono::Duration::days(90);
+        let statuses = [
+            Status {
+                domain: "Healthy".into(),
+                valid: true,
+                not_after: Some(not_after),
+                ..Default::default()
+            },
+            Status {
+                domain: "broken.com".into(),
+                valid: false,
+                not_after: Some(not_after),
+                ..Default::default()
+            },
+        ];
+        let domains = [r#""Healthy" = healthy.com"#, "broken.com"].map(String::from);
+        remember(&store, "checks", &domains, &statuses, now())
+            .await
+            .unwrap();
+        let checks = store.checks.lock().unwrap();
+        assert_eq!(checks.len(), 1);
+        assert_eq!(
+            checks[r#""Healthy" = healthy.com"#],
+            LastCheck {
+                checked_at: now(),
+                not_after
+            }
+        );
+    }
+}
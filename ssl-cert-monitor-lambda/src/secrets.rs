@@ -0,0 +1,136 @@
+use crate::cert::{parse_pem_chain, Validator};
+use crate::error::MonitorError;
+use crate::{domain_status, Status};
+use lambda_runtime::tracing::info;
+use lambda_runtime::Error;
+use rustls::pki_types::CertificateDer;
+
+/// Prefix of config lines validating a PEM bundle stored in Secrets Manager
+pub const SECRETS_MANAGER_PREFIX: &str = "secretsmanager://";
+
+/// Whether the config line refers to a Secrets Manager secret
+pub fn is_secret(domain: &str) -> bool {
+    domain.starts_with(SECRETS_MANAGER_PREFIX)
+}
+
+/// Name or ARN of the secret from a `secretsmanager://name` line
+pub fn secret_name(domain: &str) -> Result<&str, MonitorError> {
+    domain
+        .strip_prefix(SECRETS_MANAGER_PREFIX)
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| MonitorError::Config(format!("Wrong secret location {}", domain)))
+}
+
+/// Split a PEM bundle stored in a secret into DER certificates in order.
+/// Private keys stored along with the certificates are skipped.
+pub fn secret_chain(secret: &[u8]) -> Result<Vec<CertificateDer<'static>>, MonitorError> {
+    let chain =
+        parse_pem_chain(secret).map_err(|err| MonitorError::Certificate(err.to_string()))?;
+    if chain.is_empty() {
+        return Err(MonitorError::Certificate(
+            "No certificate found in secret".into(),
+        ));
+    }
+    Ok(chain)
+}
+
+/// Read the PEM bundle of a secret, stored either as a string or as binary
+async fn fetch_secret(
+    client: &aws_sdk_secretsmanager::Client,
+    name: &str,
+) -> Result<Vec<u8>, Error> {
+    info!("Read certificates from secret {}", name);
+    let output = client
+        .get_secret_value()
+        .secret_id(name)
+        .send()
+        .await
+        .map_err(|err| -> Error {
+            if err
+                .as_service_error()
+                .is_some_and(|err| err.is_resource_not_found_exception())
+            {
+                MonitorError::Config(format!("Secret {} does not exist", name)).into()
+            } else {
+                Box::new(err).into()
+            }
+        })?;
+    Ok(match (output.secret_string(), output.secret_binary()) {
+        (Some(secret), _) => secret.as_bytes().to_vec(),
+        (None, Some(secret)) => secret.as_ref().to_vec(),
+        (None, None) => Vec::new(),
+    })
+}
+
+/// Validate PEM bundles of secrets without any network handshake,
+/// reporting each under its config line
+pub async fn check_secrets(
+    client: &aws_sdk_secretsmanager::Client,
+    validator: &Validator,
+    domains: Vec<String>,
+) -> Vec<Status> {
+    let mut statuses = Vec::with_capacity(domains.len());
+    for domain in domains {
+        let res = match secret_name(&domain) {
+            Ok(name) => match fetch_secret(client, name).await {
+                Ok(secret) => {
+                    secret_chain(&secret).and_then(|chain| validator.validate_bundle(&chain))
+                }
+                Err(err) => Err(MonitorError::Config(format!(
+                    "Cannot read secret {}: {}",
+                    name, err
+                ))),
+            },
+            Err(err) => Err(err),
+        };
+        statuses.push(domain_status(domain, res));
+    }
+    statuses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_name() {
+        assert!(is_secret("secretsmanager://prod/tls/bundle"));
+        assert!(!is_secret("example.com"));
+        for (domain, name) in [
+            ("secretsmanager://prod/tls/bundle", "prod/tls/bundle"),
+            ("secretsmanager://bundle ", "bundle"),
+            (
+                "secretsmanager://arn:aws:secretsmanager:us-east-1:123456789012:secret:bundle-AbCdEf",
+                "arn:aws:secretsmanager:us-east-1:123456789012:secret:bundle-AbCdEf",
+            ),
+        ] {
+            assert_eq!(secret_name(domain).unwrap(), name, "{}", domain);
+        }
+        for domain in ["secretsmanager://", "secretsmanager://  ", "example.com"] {
+            assert!(
+                matches!(secret_name(domain), Err(MonitorError::Config(_))),
+                "{}",
+                domain
+            );
+        }
+    }
+
+    #[test]
+    fn test_secret_chain() {
+        let chain = include_str!("./data/localhost-chain.pem");
+        let key = include_str!("./data/localhost-key.pem");
+        let certificates = secret_chain(format!("{}{}", key, chain).as_bytes()).unwrap();
+        assert_eq!(certificates, parse_pem_chain(chain.as_bytes()).unwrap());
+        assert_eq!(certificates.len(), 2);
+
+        assert!(matches!(
+            secret_chain(key.as_bytes()),
+            Err(MonitorError::Certificate(_))
+        ));
+        assert!(matches!(
+            secret_chain(b"-----BEGIN CERTIFICATE-----\nnot base64\n-----END CERTIFICATE-----\n"),
+            Err(MonitorError::Certificate(_))
+        ));
+    }
+}
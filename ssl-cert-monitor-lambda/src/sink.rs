@@ -0,0 +1,138 @@
+use lambda_runtime::tracing::{info, warn};
+use lambda_runtime::Error;
+use std::future::Future;
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinSet;
+
+/// Time reserved before the invocation deadline to return a response
+const DEADLINE_MARGIN: Duration = Duration::from_secs(1);
+
+/// Pending sends to external sinks like history, metrics or notifications
+#[derive(Default)]
+pub struct Sinks {
+    tasks: JoinSet<(&'static str, Result<(), Error>)>,
+    /// Names of sinks not completed yet
+    pending: Vec<&'static str>,
+}
+
+impl Sinks {
+    /// Start sending in background
+    pub fn spawn<F>(&mut self, name: &'static str, send: F)
+    where
+        F: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        info!("Sending to {}", name);
+        self.pending.push(name);
+        self.tasks.spawn(async move { (name, send.await) });
+    }
+
+    /// Wait for all pending sends to complete within `timeout`.
+    /// Sinks are best effort, so failures are logged and returned as messages
+    /// instead of failing the invocation.
+    pub async fn flush(mut self, timeout: Duration) -> Vec<String> {
+        let total = self.pending.len();
+        let mut errors = Vec::new();
+        let all_sent = async {
+            while let Some(res) = self.tasks.join_next().await {
+                match res {
+                    Ok((name, res)) => {
+                        self.pending.retain(|pending| *pending != name);
+                        if let Err(err) = res {
+                            errors.push(format!("{}: {}", name, err));
+                        }
+                    }
+                    Err(err) => errors.push(format!("sink task failed: {}", err)),
+                }
+            }
+        };
+        if tokio::time::timeout(timeout, all_sent).await.is_err() {
+            self.tasks.abort_all();
+            errors.extend(
+                self.pending
+                    .iter()
+                    .map(|name| format!("{}: timed out after {:?}", name, timeout)),
+            );
+        }
+        for error in &errors {
+            warn!("Cannot send to sink {}", error);
+        }
+        info!("Flushed {} of {} sinks", total - errors.len(), total);
+        errors
+    }
+}
+
+/// Time left to flush sinks before the invocation deadline
+pub fn flush_timeout(deadline: SystemTime, now: SystemTime) -> Duration {
+    deadline
+        .duration_since(now)
+        .unwrap_or_default()
+        .saturating_sub(DEADLINE_MARGIN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::MonitorError;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_flush_awaits_all() {
+        let sent = Arc::new(AtomicUsize::new(0));
+        let mut sinks = Sinks::default();
+        for delay in [30, 10, 20] {
+            let sent = sent.clone();
+            sinks.spawn("test", async move {
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                sent.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            });
+        }
+        assert!(sinks.flush(Duration::from_secs(5)).await.is_empty());
+        assert_eq!(sent.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_flush_error() {
+        let mut sinks = Sinks::default();
+        sinks.spawn("ok", async { Ok(()) });
+        sinks.spawn("failing", async {
+            Err(MonitorError::General("unavailable".into()).into())
+        });
+        assert_eq!(
+            sinks.flush(Duration::from_secs(5)).await,
+            vec!["failing: general error: unavailable"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_flush_timeout() {
+        let mut sinks = Sinks::default();
+        sinks.spawn("fast", async { Ok(()) });
+        sinks.spawn("slow", async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(())
+        });
+        assert_eq!(
+            sinks.flush(Duration::from_millis(100)).await,
+            vec!["slow: timed out after 100ms"]
+        );
+    }
+
+    #[test]
+    fn test_flush_timeout_from_deadline() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        assert_eq!(
+            flush_timeout(now + Duration::from_secs(10), now),
+            Duration::from_secs(9)
+        );
+        assert_eq!(
+            flush_timeout(now + Duration::from_millis(500), now),
+            Duration::ZERO
+        );
+        assert_eq!(
+            flush_timeout(now - Duration::from_secs(1), now),
+            Duration::ZERO
+        );
+    }
+}
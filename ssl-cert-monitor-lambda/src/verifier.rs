@@ -0,0 +1,342 @@
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+/// Server certificate verifier for a single connection that delegates
+/// verification and records what the server presented during the handshake
+#[derive(Debug)]
+pub struct RecordingVerifier {
+    inner: Arc<dyn ServerCertVerifier>,
+    intermediates: Vec<CertificateDer<'static>>,
+    leaf_only: bool,
+    clock_skew: Duration,
+    ocsp_stapled: AtomicBool,
+    chain_completed: AtomicBool,
+    expired_leaf: Mutex<Option<CertificateDer<'static>>>,
+}
+
+impl RecordingVerifier {
+    pub fn new(inner: Arc<dyn ServerCertVerifier>) -> Self {
+        Self {
+            inner,
+            intermediates: Vec::new(),
+            leaf_only: false,
+            clock_skew: Duration::ZERO,
+            ocsp_stapled: AtomicBool::new(false),
+            chain_completed: AtomicBool::new(false),
+            expired_leaf: Mutex::new(None),
+        }
+    }
+
+    /// Supplement intermediates presented by servers that do not send the full chain
+    pub fn with_intermediates(mut self, intermediates: Vec<CertificateDer<'static>>) -> Self {
+        self.intermediates = intermediates;
+        self
+    }
+
+    /// Accept any presented chain to read the leaf certificate only,
+    /// while handshake signatures are still verified
+    pub fn with_leaf_only(mut self, leaf_only: bool) -> Self {
+        self.leaf_only = leaf_only;
+        self
+    }
+
+    /// Accept certificates whose validity starts or ends within `clock_skew`
+    /// of the verification time, like a freshly issued certificate
+    pub fn with_clock_skew(mut self, clock_skew: Duration) -> Self {
+        self.clock_skew = clock_skew;
+        self
+    }
+
+    /// Whether the server stapled an OCSP response
+    pub fn ocsp_stapled(&self) -> bool {
+        self.ocsp_stapled.load(Ordering::SeqCst)
+    }
+
+    /// Whether the presented chain verified only with the supplemented intermediates
+    pub fn chain_completed(&self) -> bool {
+        self.chain_completed.load(Ordering::SeqCst)
+    }
+
+    /// Leaf certificate rejected because it expired, if any
+    pub fn expired_leaf(&self) -> Option<CertificateDer<'static>> {
+        self.expired_leaf
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    fn verify_chain(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if self.intermediates.is_empty() {
+            return self.inner.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                ocsp_response,
+                now,
+            );
+        }
+        let presented = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        );
+        if presented.is_ok() {
+            return presented;
+        }
+        let mut chain = intermediates.to_vec();
+        chain.extend(self.intermediates.iter().cloned());
+        let completed =
+            self.inner
+                .verify_server_cert(end_entity, &chain, server_name, ocsp_response, now)?;
+        self.chain_completed.store(true, Ordering::SeqCst);
+        Ok(completed)
+    }
+}
+
+impl ServerCertVerifier for RecordingVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        self.ocsp_stapled
+            .store(!ocsp_response.is_empty(), Ordering::SeqCst);
+        if self.leaf_only {
+            return Ok(ServerCertVerified::assertion());
+        }
+        let verify =
+            |now| self.verify_chain(end_entity, intermediates, server_name, ocsp_response, now);
+        let res = verify(now);
+        // Verify again at the other end of the skew, keeping the original error
+        let skewed = match &res {
+            Err(rustls::Error::InvalidCertificate(
+                rustls::CertificateError::NotValidYet
+                | rustls::CertificateError::NotValidYetContext { .. },
+            )) => Some(now.as_secs().saturating_add(self.clock_skew.as_secs())),
+            Err(rustls::Error::InvalidCertificate(
+                rustls::CertificateError::Expired | rustls::CertificateError::ExpiredContext { .. },
+            )) => Some(now.as_secs().saturating_sub(self.clock_skew.as_secs())),
+            _ => None,
+        };
+        let res = match skewed {
+            Some(secs) if !self.clock_skew.is_zero() => {
+                verify(UnixTime::since_unix_epoch(Duration::from_secs(secs))).or(res)
+            }
+            _ => res,
+        };
+        if let Err(rustls::Error::InvalidCertificate(
+            rustls::CertificateError::Expired | rustls::CertificateError::ExpiredContext { .. },
+        )) = &res
+        {
+            // Handshake fails before the leaf is read, so keep it to tell its age
+            *self
+                .expired_leaf
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner) = Some(end_entity.clone().into_owned());
+        }
+        res
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::client::WebPkiServerVerifier;
+    use rustls::pki_types::pem::PemObject;
+
+    fn webpki_verifier() -> Arc<dyn ServerCertVerifier> {
+        let root_store =
+            rustls::RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        WebPkiServerVerifier::builder(Arc::new(root_store))
+            .build()
+            .unwrap()
+    }
+
+    fn verify(verifier: &RecordingVerifier, ocsp_response: &[u8]) {
+        let leaf = CertificateDer::from(Vec::<u8>::from(include_bytes!("./data/cert-expired.der")));
+        let intermediate =
+            CertificateDer::from(Vec::<u8>::from(include_bytes!("./data/cert-2031.der")));
+        // Expired certificate is rejected, but the stapled response is still recorded
+        let res = verifier.verify_server_cert(
+            &leaf,
+            &[intermediate],
+            &ServerName::try_from("statehouse.gov.ng").unwrap(),
+            ocsp_response,
+            UnixTime::now(),
+        );
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_ocsp_stapled() {
+        let verifier = RecordingVerifier::new(webpki_verifier());
+        verify(&verifier, b"ocsp response");
+        assert!(verifier.ocsp_stapled());
+    }
+
+    #[test]
+    fn test_ocsp_not_stapled() {
+        let verifier = RecordingVerifier::new(webpki_verifier());
+        verify(&verifier, b"");
+        assert!(!verifier.ocsp_stapled());
+    }
+
+    /// Verifier accepting any chain within a validity period
+    #[derive(Debug)]
+    struct ValidityVerifier {
+        inner: Arc<dyn ServerCertVerifier>,
+        not_before: u64,
+        not_after: u64,
+    }
+
+    impl ServerCertVerifier for ValidityVerifier {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            now: UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            if now.as_secs() < self.not_before {
+                Err(rustls::CertificateError::NotValidYet.into())
+            } else if now.as_secs() > self.not_after {
+                Err(rustls::CertificateError::Expired.into())
+            } else {
+                Ok(ServerCertVerified::assertion())
+            }
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            self.inner.verify_tls12_signature(message, cert, dss)
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            self.inner.verify_tls13_signature(message, cert, dss)
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.inner.supported_verify_schemes()
+        }
+    }
+
+    #[test]
+    fn test_clock_skew() {
+        let inner: Arc<dyn ServerCertVerifier> = Arc::new(ValidityVerifier {
+            inner: webpki_verifier(),
+            not_before: 1_000_000,
+            not_after: 2_000_000,
+        });
+        let leaf = CertificateDer::from(&include_bytes!("./data/cert-2031.der")[..]);
+        let verify = |verifier: &RecordingVerifier, secs: u64| {
+            verifier.verify_server_cert(
+                &leaf,
+                &[],
+                &ServerName::try_from("example.com").unwrap(),
+                b"",
+                UnixTime::since_unix_epoch(Duration::from_secs(secs)),
+            )
+        };
+
+        let strict = RecordingVerifier::new(inner.clone());
+        let skewed = RecordingVerifier::new(inner).with_clock_skew(Duration::from_secs(300));
+        // Issued 2 minutes ahead of our clock
+        assert!(verify(&strict, 1_000_000 - 120).is_err());
+        assert!(verify(&skewed, 1_000_000 - 120).is_ok());
+        assert!(verify(&skewed, 1_000_000 - 301).is_err());
+        // Expired 2 minutes ago
+        assert!(verify(&strict, 2_000_000 + 120).is_err());
+        assert!(strict.expired_leaf().is_some());
+        assert!(verify(&skewed, 2_000_000 + 120).is_ok());
+        assert!(skewed.expired_leaf().is_none());
+        assert!(matches!(
+            verify(&skewed, 2_000_000 + 301),
+            Err(rustls::Error::InvalidCertificate(
+                rustls::CertificateError::Expired
+            ))
+        ));
+        assert!(skewed.expired_leaf().is_some());
+    }
+
+    #[test]
+    fn test_injected_intermediate() {
+        let mut root_store = rustls::RootCertStore::empty();
+        root_store
+            .add(CertificateDer::from(
+                &include_bytes!("./data/internal-root.der")[..],
+            ))
+            .unwrap();
+        let inner: Arc<dyn ServerCertVerifier> =
+            WebPkiServerVerifier::builder(Arc::new(root_store))
+                .build()
+                .unwrap();
+        let leaf = CertificateDer::from(&include_bytes!("./data/internal-leaf.der")[..]);
+        let intermediates =
+            CertificateDer::pem_slice_iter(include_bytes!("./data/internal-intermediate.pem"))
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+        let verify = |verifier: &RecordingVerifier| {
+            verifier.verify_server_cert(
+                &leaf,
+                &[],
+                &ServerName::try_from("internal.example.com").unwrap(),
+                b"",
+                UnixTime::now(),
+            )
+        };
+
+        // Server presents only the leaf
+        assert!(verify(&RecordingVerifier::new(inner.clone())).is_err());
+        let verifier = RecordingVerifier::new(inner).with_intermediates(intermediates);
+        assert!(verify(&verifier).is_ok());
+        assert!(verifier.chain_completed());
+    }
+}
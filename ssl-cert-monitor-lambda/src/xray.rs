@@ -0,0 +1,204 @@
+use lambda_runtime::tracing::{self, info, Instrument};
+use rand::Rng;
+use serde::Serialize;
+use std::future::Future;
+use std::net::UdpSocket;
+use std::sync::{Mutex, PoisonError};
+use std::time::SystemTime;
+
+/// Default address of the X-Ray daemon
+const DEFAULT_DAEMON_ADDRESS: &str = "127.0.0.1:2000";
+
+/// Header preceding every segment document sent to the daemon
+const DAEMON_HEADER: &str = "{\"format\": \"json\", \"version\": 1}\n";
+
+/// Trace of the current invocation from the `Root=...;Parent=...;Sampled=1` header
+#[derive(Debug, PartialEq)]
+pub struct TraceHeader {
+    pub root: String,
+    pub parent: String,
+    pub sampled: bool,
+}
+
+impl std::str::FromStr for TraceHeader {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut root = None;
+        let mut parent = None;
+        let mut sampled = false;
+        for part in value.split(';') {
+            match part.trim().split_once('=') {
+                Some(("Root", value)) => root = Some(value.to_string()),
+                Some(("Parent", value)) => parent = Some(value.to_string()),
+                Some(("Sampled", value)) => sampled = value == "1",
+                _ => {}
+            }
+        }
+        Ok(TraceHeader {
+            root: root.ok_or(())?,
+            parent: parent.ok_or(())?,
+            sampled,
+        })
+    }
+}
+
+/// Subsegment document as accepted by the X-Ray daemon
+#[derive(Debug, Clone, Serialize)]
+pub struct Subsegment {
+    pub name: String,
+    pub id: String,
+    pub trace_id: String,
+    pub parent_id: String,
+    pub start_time: f64,
+    pub end_time: f64,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+}
+
+fn epoch_seconds() -> f64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_secs_f64())
+        .unwrap_or_default()
+}
+
+/// Random 64-bit identifier in hex
+fn subsegment_id() -> String {
+    format!("{:016x}", rand::thread_rng().gen::<u64>())
+}
+
+/// Records timings of operations as X-Ray subsegments of the invocation
+pub struct Recorder {
+    trace: TraceHeader,
+    subsegments: Mutex<Vec<Subsegment>>,
+}
+
+impl Recorder {
+    pub fn new(trace: TraceHeader) -> Self {
+        Self {
+            trace,
+            subsegments: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Recorder for a sampled invocation when `XRAY_TRACING` is enabled
+    pub fn from_env(trace_header: Option<&str>) -> Option<Self> {
+        let enabled = std::env::var("XRAY_TRACING")
+            .map(|value| value == "true")
+            .unwrap_or(false);
+        let trace: TraceHeader = trace_header?.parse().ok()?;
+        (enabled && trace.sampled).then(|| Self::new(trace))
+    }
+
+    fn push(&self, name: &str, start_time: f64) {
+        let subsegment = Subsegment {
+            name: name.to_string(),
+            id: subsegment_id(),
+            trace_id: self.trace.root.clone(),
+            parent_id: self.trace.parent.clone(),
+            start_time,
+            end_time: epoch_seconds(),
+            kind: "subsegment",
+        };
+        // Keep recording even if a traced domain check panicked
+        self.subsegments
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(subsegment);
+    }
+
+    /// Run `f` within a tracing span and record it as a subsegment
+    pub fn record<T>(&self, name: &str, f: impl FnOnce() -> T) -> T {
+        let start_time = epoch_seconds();
+        let result = tracing::info_span!("subsegment", name).in_scope(f);
+        self.push(name, start_time);
+        result
+    }
+
+    /// Await `future` within a tracing span and record it as a subsegment
+    pub async fn record_async<T>(&self, name: &str, future: impl Future<Output = T>) -> T {
+        let start_time = epoch_seconds();
+        let result = future
+            .instrument(tracing::info_span!("subsegment", name))
+            .await;
+        self.push(name, start_time);
+        result
+    }
+
+    pub fn subsegments(&self) -> Vec<Subsegment> {
+        self.subsegments
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Send recorded subsegments to the X-Ray daemon
+    pub fn send(&self) -> std::io::Result<()> {
+        let address = std::env::var("AWS_XRAY_DAEMON_ADDRESS")
+            .unwrap_or_else(|_| DEFAULT_DAEMON_ADDRESS.to_string());
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        let subsegments = self.subsegments();
+        for subsegment in &subsegments {
+            let document = DAEMON_HEADER.to_string() + &serde_json::to_string(subsegment)?;
+            socket.send_to(document.as_bytes(), &address)?;
+        }
+        info!("Sent {} X-Ray subsegments", subsegments.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace() -> TraceHeader {
+        "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1"
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_trace_header() {
+        assert_eq!(
+            trace(),
+            TraceHeader {
+                root: "1-5759e988-bd862e3fe1be46a994272793".into(),
+                parent: "53995c3f42cd8ad8".into(),
+                sampled: true,
+            }
+        );
+        assert!("Root=1-5759e988-bd862e3fe1be46a994272793"
+            .parse::<TraceHeader>()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_record_subsegments() {
+        let recorder = Recorder::new(trace());
+        let domains = recorder
+            .record_async("s3_fetch", async { vec!["a.com", "b.com"] })
+            .await;
+        for domain in domains {
+            assert_eq!(recorder.record(domain, || domain.len()), 5);
+        }
+
+        let subsegments = recorder.subsegments();
+        assert_eq!(
+            subsegments
+                .iter()
+                .map(|subsegment| subsegment.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["s3_fetch", "a.com", "b.com"]
+        );
+        for subsegment in &subsegments {
+            assert_eq!(subsegment.trace_id, "1-5759e988-bd862e3fe1be46a994272793");
+            assert_eq!(subsegment.parent_id, "53995c3f42cd8ad8");
+            assert_eq!(subsegment.id.len(), 16);
+            assert!(subsegment.id.chars().all(|c| c.is_ascii_hexdigit()));
+            assert!(subsegment.start_time <= subsegment.end_time);
+        }
+        let document = serde_json::to_value(&subsegments[0]).unwrap();
+        assert_eq!(document["type"], "subsegment");
+    }
+}
@@ -0,0 +1,206 @@
+use crate::{needs_attention, Request, Status};
+use aws_sdk_s3::Client;
+use lambda_runtime::tracing::info;
+use lambda_runtime::Error;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+/// Changes of failing domains since the previous run
+#[derive(Debug, Default, PartialEq)]
+pub struct RunDiff {
+    /// Failing now, healthy or not checked before
+    pub new: Vec<String>,
+    /// Failing before, healthy now
+    pub recovered: Vec<String>,
+    /// Failing in both runs
+    pub ongoing: Vec<String>,
+}
+
+/// Domains with unsuppressed failures or warnings
+pub fn failing_domains(statuses: &[Status]) -> BTreeSet<String> {
+    statuses
+        .iter()
+        .filter(|status| needs_attention(status))
+        .map(|status| status.domain.clone())
+        .collect()
+}
+
+/// Compare failing domains of the previous and the current runs
+pub fn compare(previous: &BTreeSet<String>, current: &BTreeSet<String>) -> RunDiff {
+    RunDiff {
+        new: current.difference(previous).cloned().collect(),
+        recovered: previous.difference(current).cloned().collect(),
+        ongoing: current.intersection(previous).cloned().collect(),
+    }
+}
+
+/// Render non-empty sections of the diff, `None` when nothing failed in both runs
+pub fn render(diff: &RunDiff) -> Option<String> {
+    let mut message = String::new();
+    for (title, domains) in [
+        ("New issues", &diff.new),
+        ("Recovered", &diff.recovered),
+        ("Ongoing", &diff.ongoing),
+    ] {
+        if domains.is_empty() {
+            continue;
+        }
+        if !message.is_empty() {
+            message.push('\n');
+        }
+        let _ = write!(message, "{} ({}):", title, domains.len());
+        for domain in domains {
+            let _ = write!(message, "\n- {}", domain);
+        }
+    }
+    (!message.is_empty()).then_some(message)
+}
+
+/// Extract bucket and key from an `s3://bucket/key` location
+fn parse_s3_location(location: &str) -> Result<(&str, &str), Error> {
+    location
+        .strip_prefix("s3://")
+        .and_then(|path| path.split_once('/'))
+        .filter(|(bucket, key)| !bucket.is_empty() && !key.is_empty())
+        .ok_or_else(|| format!("Cannot parse S3 url {}", location).into())
+}
+
+/// Load the monitor response stored by the previous run, `None` on the first run
+pub async fn load_previous(client: &Client, location: &str) -> Result<Option<Request>, Error> {
+    let (bucket, key) = parse_s3_location(location)?;
+    let object = match client.get_object().bucket(bucket).key(key).send().await {
+        Ok(object) => object,
+        Err(err)
+            if err
+                .as_service_error()
+                .is_some_and(|err| err.is_no_such_key()) =>
+        {
+            info!("No previous report at {}", location);
+            return Ok(None);
+        }
+        Err(err) => return Err(Box::new(err).into()),
+    };
+    let body = object.body.collect().await?.into_bytes();
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+/// Store the monitor response to compare the next run against
+pub async fn save(client: &Client, location: &str, report: &Request) -> Result<(), Error> {
+    let (bucket, key) = parse_s3_location(location)?;
+    client
+        .put_object()
+        .bucket(bucket)
+        .key(key)
+        .content_type("application/json")
+        .body(serde_json::to_vec(report)?.into())
+        .send()
+        .await
+        .map_err(Box::new)?;
+    info!("Stored report to {}", location);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn domains(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_failing_domains() {
+        let statuses = [
+            Status {
+                domain: "broken.com".into(),
+                valid: false,
+                ..Default::default()
+            },
+            Status {
+                domain: "muted.com".into(),
+                valid: false,
+                suppressed: true,
+                ..Default::default()
+            },
+            Status {
+                domain: "distrusted.com".into(),
+                valid: true,
+                distrusted_issuer: Some("Old CA".into()),
+                ..Default::default()
+            },
+            Status {
+                domain: "healthy.com".into(),
+                valid: true,
+                ..Default::default()
+            },
+        ];
+        assert_eq!(
+            failing_domains(&statuses),
+            domains(&["broken.com", "distrusted.com"])
+        );
+    }
+
+    #[test]
+    fn test_compare() {
+        let diff = compare(
+            &domains(&["fixed.com", "still.com", "also-fixed.com"]),
+            &domains(&["still.com", "broken.com"]),
+        );
+        assert_eq!(
+            diff,
+            RunDiff {
+                new: vec!["broken.com".into()],
+                recovered: vec!["also-fixed.com".into(), "fixed.com".into()],
+                ongoing: vec!["still.com".into()],
+            }
+        );
+
+        let diff = compare(&domains(&[]), &domains(&["broken.com"]));
+        assert_eq!(diff.new, vec!["broken.com"]);
+        assert!(diff.recovered.is_empty() && diff.ongoing.is_empty());
+
+        let diff = compare(&domains(&["fixed.com"]), &domains(&[]));
+        assert_eq!(diff.recovered, vec!["fixed.com"]);
+        assert!(diff.new.is_empty() && diff.ongoing.is_empty());
+
+        assert_eq!(compare(&domains(&[]), &domains(&[])), RunDiff::default());
+    }
+
+    #[test]
+    fn test_render() {
+        let diff = compare(
+            &domains(&["fixed.com", "still.com"]),
+            &domains(&["still.com", "broken.com"]),
+        );
+        assert_eq!(
+            render(&diff).as_deref(),
+            Some(
+                "New issues (1):\n- broken.com\n\
+                 Recovered (1):\n- fixed.com\n\
+                 Ongoing (1):\n- still.com"
+            )
+        );
+        let diff = compare(&domains(&["fixed.com"]), &domains(&[]));
+        assert_eq!(
+            render(&diff).as_deref(),
+            Some("Recovered (1):\n- fixed.com")
+        );
+        assert_eq!(render(&RunDiff::default()), None);
+    }
+
+    #[test]
+    fn test_parse_s3_location() {
+        assert_eq!(
+            parse_s3_location("s3://bucket/reporter/previous.json").unwrap(),
+            ("bucket", "reporter/previous.json")
+        );
+        for location in [
+            "s3://bucket",
+            "s3://bucket/",
+            "s3:///key",
+            "https://bucket/key",
+        ] {
+            assert!(parse_s3_location(location).is_err(), "{}", location);
+        }
+    }
+}
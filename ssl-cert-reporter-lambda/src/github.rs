@@ -0,0 +1,237 @@
+use crate::{describe, needs_attention, tier_label, tier_rank, Status};
+use lambda_runtime::tracing::info;
+use lambda_runtime::Error;
+use serde_json::{json, Value};
+
+/// Stable title used to find the issue opened by previous runs
+pub const ISSUE_TITLE: &str = "SSL certificate issues";
+
+/// Label of issues opened by the reporter, so only they are listed
+pub const ISSUE_LABEL: &str = "ssl-cert-monitor";
+
+const API_URL: &str = "https://api.github.com";
+
+/// Issues listed per page, the maximum allowed by GitHub
+const PER_PAGE: usize = 100;
+
+/// Maximal length of an issue body accepted by GitHub, in characters
+pub const MAX_BODY_CHARS: usize = 65536;
+
+/// Note appended to a body cut to fit into `MAX_BODY_CHARS`
+const TRUNCATED_NOTE: &str = "\n...truncated\n";
+
+/// Build a Markdown issue body listing failing domains and valid ones with warnings
+pub fn build_body(statuses: &[Status]) -> String {
+    let mut failed: Vec<&Status> = statuses
+        .iter()
+        .filter(|status| needs_attention(status))
+        .collect();
+    failed.sort_by_key(|status| tier_rank(status));
+    let lines: Vec<String> = failed
+        .into_iter()
+        .map(|status| {
+            format!(
+                "- [ ] {}`{}`: {}",
+                tier_label(status),
+                status.domain,
+                describe(status)
+            )
+        })
+        .collect();
+    truncate_body(format!(
+        "Found {} SSL certificate issues:\n\n{}\n",
+        lines.len(),
+        lines.join("\n")
+    ))
+}
+
+/// Cut a body longer than `MAX_BODY_CHARS` at a line boundary
+fn truncate_body(body: String) -> String {
+    if body.chars().count() <= MAX_BODY_CHARS {
+        return body;
+    }
+    let limit = MAX_BODY_CHARS - TRUNCATED_NOTE.chars().count();
+    let end = body
+        .char_indices()
+        .nth(limit)
+        .map_or(body.len(), |(index, _)| index);
+    let end = body[..end].rfind('\n').unwrap_or(end);
+    format!("{}{}", &body[..end], TRUNCATED_NOTE)
+}
+
+/// Number of the open issue with the given title among listed issues.
+/// Pull requests are listed as issues too, so they are skipped.
+pub fn find_issue(issues: &[Value], title: &str) -> Option<u64> {
+    issues
+        .iter()
+        .filter(|issue| issue.get("pull_request").is_none())
+        .find(|issue| issue["title"] == title)
+        .and_then(|issue| issue["number"].as_u64())
+}
+
+fn request(
+    client: &reqwest::Client,
+    method: reqwest::Method,
+    url: &str,
+    token: &str,
+) -> reqwest::RequestBuilder {
+    client
+        .request(method, url)
+        .bearer_auth(token)
+        .header("accept", "application/vnd.github+json")
+        // GitHub rejects requests without a user agent
+        .header("user-agent", env!("CARGO_PKG_NAME"))
+}
+
+/// Open or update the issue in `repo` (`owner/name`) while there are failing
+/// domains and close it once everything is healthy again
+pub async fn sync(
+    repo: &str,
+    token: &str,
+    statuses: &[Status],
+    failing: bool,
+) -> Result<(), Error> {
+    let client = reqwest::Client::new();
+    let issues_url = format!("{}/repos/{}/issues", API_URL, repo);
+    // Only issues of the reporter are listed, page by page until a partial one
+    let mut existing = None;
+    for page in 1.. {
+        let issues: Vec<Value> = request(&client, reqwest::Method::GET, &issues_url, token)
+            .query(&[
+                ("state", "open"),
+                ("labels", ISSUE_LABEL),
+                ("per_page", &PER_PAGE.to_string()),
+                ("page", &page.to_string()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        existing = find_issue(&issues, ISSUE_TITLE);
+        if existing.is_some() || issues.len() < PER_PAGE {
+            break;
+        }
+    }
+
+    match (existing, failing) {
+        (Some(number), true) => {
+            request(
+                &client,
+                reqwest::Method::PATCH,
+                &format!("{}/{}", issues_url, number),
+                token,
+            )
+            .json(&json!({ "body": build_body(statuses) }))
+            .send()
+            .await?
+            .error_for_status()?;
+            info!("Updated GitHub issue #{} in {}", number, repo);
+        }
+        (None, true) => {
+            request(&client, reqwest::Method::POST, &issues_url, token)
+                .json(&json!({
+                    "title": ISSUE_TITLE,
+                    "body": build_body(statuses),
+                    "labels": [ISSUE_LABEL]
+                }))
+                .send()
+                .await?
+                .error_for_status()?;
+            info!("Opened GitHub issue in {}", repo);
+        }
+        (Some(number), false) => {
+            request(
+                &client,
+                reqwest::Method::PATCH,
+                &format!("{}/{}", issues_url, number),
+                token,
+            )
+            .json(&json!({ "state": "closed", "state_reason": "completed" }))
+            .send()
+            .await?
+            .error_for_status()?;
+            info!("Closed GitHub issue #{} in {}", number, repo);
+        }
+        (None, false) => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_body() {
+        let body = build_body(&[
+            Status {
+                domain: "foobar".into(),
+                valid: false,
+                error: "oops".into(),
+                ..Default::default()
+            },
+            Status {
+                domain: "baz".into(),
+                valid: false,
+                error: "expired".into(),
+                tier: Some("critical".into()),
+                ..Default::default()
+            },
+            Status {
+                domain: "muted".into(),
+                valid: false,
+                error: "expired".into(),
+                suppressed: true,
+                ..Default::default()
+            },
+            Status {
+                domain: "healthy".into(),
+                valid: true,
+                ..Default::default()
+            },
+        ]);
+        assert_eq!(
+            body,
+            "Found 2 SSL certificate issues:\n\n\
+             - [ ] [critical] `baz`: expired\n\
+             - [ ] `foobar`: oops\n"
+        );
+    }
+
+    #[test]
+    fn test_find_issue() {
+        let issues = vec![
+            json!({ "number": 1, "title": "Unrelated" }),
+            json!({ "number": 2, "title": ISSUE_TITLE, "pull_request": {} }),
+            json!({ "number": 3, "title": ISSUE_TITLE }),
+            json!({ "number": 4, "title": ISSUE_TITLE }),
+        ];
+        assert_eq!(find_issue(&issues, ISSUE_TITLE), Some(3));
+        assert_eq!(find_issue(&issues[..2], ISSUE_TITLE), None);
+        assert_eq!(find_issue(&[], ISSUE_TITLE), None);
+    }
+
+    #[test]
+    fn test_build_body_truncated() {
+        let statuses: Vec<Status> = (0..2000)
+            .map(|i| Status {
+                domain: format!("d{}.example.com", i),
+                valid: false,
+                error: "network error: connection refused".into(),
+                ..Default::default()
+            })
+            .collect();
+        let body = build_body(&statuses);
+        assert!(body.chars().count() <= MAX_BODY_CHARS);
+        assert!(body.starts_with("Found 2000 SSL certificate issues"));
+        assert!(
+            body.ends_with("connection refused\n...truncated\n"),
+            "{}",
+            body
+        );
+
+        let short = "Found 1 SSL certificate issues:\n\n- [ ] `a.com`: oops\n";
+        assert_eq!(truncate_body(short.to_string()), short);
+    }
+}
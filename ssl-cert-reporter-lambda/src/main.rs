@@ -1,23 +1,93 @@
+mod diff;
+mod github;
+mod teams;
+
 use lambda_runtime::{run, service_fn, tracing, Error, LambdaEvent};
 
-use lambda_runtime::tracing::info;
-use serde::{Deserialize, Serialize};
+use aws_config::meta::region::RegionProviderChain;
+use chrono::{DateTime, Utc};
+use lambda_runtime::tracing::{info, warn};
+use serde::Serialize;
+use ssl_cert_monitor_common::{
+    common_failure, parse_warning_kinds, severity, CommonFailure, Severity, Status,
+};
+use std::fmt::Write;
 
 /// Requests come into the runtime as unicode
 /// strings in json format, which can map to any structure that implements `serde::Deserialize`
 /// The runtime pays no attention to the contents of the request payload.
-#[derive(Deserialize)]
-struct Request {
-    #[allow(dead_code)]
-    req_id: String,
-    statuses: Vec<Status>,
+/// The reporter consumes the response of the monitor lambda.
+type Request = ssl_cert_monitor_common::Response;
+
+/// Certificates expired within this period likely failed a renewal
+const RECENT_EXPIRY_HOURS: i64 = 48;
+
+/// Escalation hint for already expired certificates
+fn expiry_hint(status: &Status) -> &'static str {
+    match status.expired_hours_ago {
+        Some(hours) if hours <= RECENT_EXPIRY_HOURS => " [recently expired, renewal failed?]",
+        Some(_) => " [expired long ago, abandoned?]",
+        None => "",
+    }
+}
+
+/// Domain tiers from the most important, domains of other tiers go last
+const TIERS: [&str; 4] = ["critical", "high", "medium", "low"];
+
+/// Position of the domain tier to list important domains first
+fn tier_rank(status: &Status) -> usize {
+    status
+        .tier
+        .as_deref()
+        .and_then(|tier| TIERS.iter().position(|known| *known == tier))
+        .unwrap_or(TIERS.len())
+}
+
+/// Whether an unsuppressed status needs attention: a failure, or a valid
+/// certificate with warnings like a distrusted issuer. Failures are never `Ok`.
+fn needs_attention(status: &Status) -> bool {
+    !status.suppressed && Severity::of(status, &[]) > Severity::Ok
+}
+
+/// Error of a failing domain or warnings of a valid one
+fn describe(status: &Status) -> String {
+    if !status.valid {
+        return format!("{}{}", status.error, expiry_hint(status));
+    }
+    let mut warnings = Vec::new();
+    if let Some(issuer) = &status.distrusted_issuer {
+        warnings.push(format!("issued by distrusted CA {}", issuer));
+    }
+    if status.validity_exceeded {
+        warnings.push(match status.validity_days {
+            Some(days) => format!("valid for too long ({} days)", days),
+            None => "valid for too long".to_string(),
+        });
+    }
+    if let Some(alert) = &status.late_alert {
+        warnings.push(format!("fatal TLS alert after handshake: {}", alert));
+    }
+    if status.rotated {
+        warnings.push("certificate rotated unexpectedly".to_string());
+    }
+    if status.validity_shortened {
+        warnings.push("renewed for a much shorter validity period".to_string());
+    }
+    for redirect in &status.redirects {
+        if let Some(error) = &redirect.error {
+            warnings.push(format!("redirect target {}: {}", redirect.target, error));
+        }
+    }
+    warnings.join(", ")
 }
 
-#[derive(Deserialize)]
-struct Status {
-    domain: String,
-    valid: bool,
-    error: String,
+/// Label of the domain tier, if any
+fn tier_label(status: &Status) -> String {
+    status
+        .tier
+        .as_ref()
+        .map(|tier| format!("[{}] ", tier))
+        .unwrap_or_default()
 }
 
 #[derive(Serialize)]
@@ -30,30 +100,153 @@ enum Report {
 /// The runtime pays no attention to the contents of the response payload.
 #[derive(Serialize)]
 struct Response {
-    report: Report
+    report: Report,
+    severity: Severity,
+    /// Monitor results are older than `STALE_AFTER_MINUTES`
+    stale: bool,
+    /// Every domain with days remaining, included when `REPORT_VERBOSE` is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    full_report: Option<String>,
+    /// New, recovered and ongoing issues since the run stored at `PREVIOUS_REPORT_LOCATION`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    changes: Option<String>,
+    /// Failing domains grouped by the certificate issuer, most affected first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    failures_by_issuer: Vec<IssuerFailures>,
 }
 
-fn aggregate(statuses: Vec<Status>) -> Result<Report, Error> {
-    let invalid_statuses: Vec<Status> = statuses
+/// Number of failing domains with certificates of an issuer
+#[derive(Debug, PartialEq, Serialize)]
+struct IssuerFailures {
+    issuer: String,
+    count: usize,
+}
+
+/// Issuer of failures without a known certificate, like network errors
+const UNKNOWN_ISSUER: &str = "unknown";
+
+/// Group unsuppressed failures by issuer, so failures caused by a single CA,
+/// like its ACME endpoint being down, stand out
+fn failures_by_issuer(statuses: &[Status]) -> Vec<IssuerFailures> {
+    let mut counts = std::collections::BTreeMap::new();
+    for status in statuses
+        .iter()
+        .filter(|status| !status.valid && !status.suppressed)
+    {
+        let issuer = status.issuer.as_deref().unwrap_or(UNKNOWN_ISSUER);
+        *counts.entry(issuer).or_insert(0) += 1;
+    }
+    let mut failures: Vec<IssuerFailures> = counts
         .into_iter()
-        .filter(|status| !status.valid)
+        .map(|(issuer, count)| IssuerFailures {
+            issuer: issuer.to_string(),
+            count,
+        })
         .collect();
+    // Stable sort keeps issuers with equal counts in alphabetical order
+    failures.sort_by_key(|failures| std::cmp::Reverse(failures.count));
+    failures
+}
+
+/// Default age in minutes after which monitor results are considered stale
+const DEFAULT_STALE_AFTER_MINUTES: i64 = 24 * 60;
+
+/// Whether monitor results checked at `checked_at` are older than `max_age`.
+/// Results of monitors not reporting the check time are never stale.
+fn is_stale(
+    checked_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+    max_age: chrono::Duration,
+) -> bool {
+    checked_at.is_some_and(|checked_at| now - checked_at > max_age)
+}
+
+/// Default number of failing domains listed in the report
+const DEFAULT_MAX_LISTED: usize = 50;
+
+/// Issues listed one by one, at most `max_listed` of them, and the number of
+/// the rest. Failures collapsed into a common `failure` are left out.
+fn truncate<'a>(
+    issues: &[&'a Status],
+    failure: Option<&CommonFailure>,
+    max_listed: usize,
+) -> (Vec<&'a Status>, usize) {
+    let collapsed_kind = failure.map(|failure| failure.error_kind.as_str());
+    let mut issues = issues.iter().copied().filter(|status| {
+        collapsed_kind.is_none() || status.error_kind.as_deref() != collapsed_kind
+    });
+    let listed = issues.by_ref().take(max_listed).collect();
+    (listed, issues.count())
+}
+
+fn aggregate(statuses: &[Status], max_listed: usize) -> Result<Report, Error> {
+    // Keep references to unsuppressed failures and warnings only
+    let mut invalid_statuses: Vec<&Status> = Vec::new();
+    for status in statuses {
+        if needs_attention(status) {
+            invalid_statuses.push(status);
+        } else if status.suppressed && !status.valid {
+            info!(
+                "Suppressed issue for domain {} ({})",
+                status.domain, status.error
+            );
+        }
+    }
+    invalid_statuses.sort_by_key(|status| tier_rank(status));
 
     if invalid_statuses.is_empty() {
         info!("Everything is fine");
         Ok(Report::Valid(()))
     } else {
-        let message = format!("Found {} issues.\n", invalid_statuses.len())
-            + &invalid_statuses
-                .into_iter()
-                .map(|status| format!("Domain {} ({})", status.domain, status.error))
-                .collect::<Vec<_>>()
-                .join("\n");
+        let mut message = format!("Found {} issues.", invalid_statuses.len());
+        // Collapse failures sharing a cause into a single line
+        let failure = common_failure(statuses);
+        if let Some(failure) = &failure {
+            let _ = write!(message, "\n{}", failure);
+        }
+        let (listed, remaining) = truncate(&invalid_statuses, failure.as_ref(), max_listed);
+        for status in listed {
+            let _ = write!(
+                message,
+                "\n{}Domain {} ({})",
+                tier_label(status),
+                status.domain,
+                describe(status)
+            );
+        }
+        if remaining > 0 {
+            let _ = write!(message, "\n...and {} more", remaining);
+        }
         info!("Composed message {}", &message);
         Ok(Report::Invalid(message))
     }
 }
 
+/// List all domains including healthy ones, sorted by days remaining,
+/// domains without a known expiration go last
+fn render_full(statuses: &[Status]) -> String {
+    let mut sorted: Vec<&Status> = statuses.iter().collect();
+    sorted.sort_by_key(|status| (status.days_remaining.is_none(), status.days_remaining));
+    sorted
+        .into_iter()
+        .map(|status| {
+            let days = status
+                .days_remaining
+                .map(|days| format!("{} days", days))
+                .unwrap_or_else(|| "unknown".into());
+            let state = if status.valid {
+                "valid".to_string()
+            } else if status.suppressed {
+                format!("suppressed: {}", status.error)
+            } else {
+                format!("invalid: {}", status.error)
+            };
+            format!("Domain {}: {} ({})", status.domain, days, state)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 /// This is the main body for the function.
 /// Write your code inside it.
 /// There are some code example in the following URLs:
@@ -61,10 +254,116 @@ fn aggregate(statuses: Vec<Status>) -> Result<Report, Error> {
 /// - https://github.com/aws-samples/serverless-rust-demo/
 async fn function_handler(event: LambdaEvent<Request>) -> Result<Response, Error> {
     // Extract some useful info from the request
-    let report = aggregate(event.payload.statuses)?;
+    let checked_at = event.payload.checked_at;
+    let statuses = event.payload.statuses;
+    let max_listed = std::env::var("REPORT_MAX_DOMAINS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_MAX_LISTED);
+    let report = aggregate(&statuses, max_listed)?;
+    let warning_kinds = std::env::var("WARNING_ERROR_KINDS")
+        .map(|value| parse_warning_kinds(&value))
+        .unwrap_or_default();
+    let mut severity = severity(&statuses, &warning_kinds);
+    // Warnings of valid certificates are notified as well
+    let failing = severity >= Severity::Warning;
+
+    // Old results hide the current state, so report them at least as a warning
+    let stale_after = std::env::var("STALE_AFTER_MINUTES")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_STALE_AFTER_MINUTES);
+    let stale = is_stale(
+        checked_at,
+        Utc::now(),
+        chrono::Duration::minutes(stale_after),
+    );
+    if stale {
+        warn!("Monitor results checked at {:?} are stale", checked_at);
+        severity = severity.max(Severity::Warning);
+    }
+    info!("Severity {:?}", severity);
+
+    // Notify only about issues at least as severe as configured
+    let notify_severity = std::env::var("NOTIFY_SEVERITY")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(Severity::Warning);
+    if failing && severity >= notify_severity {
+        if let Ok(webhook_url) = std::env::var("TEAMS_WEBHOOK_URL") {
+            // Notifications are best-effort, the previous report is saved anyway
+            let card = teams::build_card(&statuses, max_listed);
+            if let Err(err) = teams::notify(&webhook_url, &card).await {
+                warn!("Cannot send Teams notification: {}", err);
+            }
+        }
+    }
+
+    // Track failures in a GitHub issue, closed once everything is healthy
+    if let (Ok(repo), Ok(token)) = (std::env::var("GITHUB_REPO"), std::env::var("GITHUB_TOKEN")) {
+        // Stale results do not prove recovery, so the issue stays open
+        if stale && !failing {
+            info!("Keeping GitHub issue in {} as results are stale", repo);
+        } else if !failing || severity >= notify_severity {
+            if let Err(err) = github::sync(&repo, &token, &statuses, failing).await {
+                warn!("Cannot sync GitHub issue in {}: {}", repo, err);
+            }
+        }
+    }
+
+    let verbose = std::env::var("REPORT_VERBOSE")
+        .map(|value| value == "true")
+        .unwrap_or(false);
+    let full_report = verbose.then(|| render_full(&statuses));
+
+    let failures_by_issuer = failures_by_issuer(&statuses);
+    for failures in &failures_by_issuer {
+        info!(
+            "{} failures of {} certificates",
+            failures.count, failures.issuer
+        );
+    }
+
+    // Tell what changed since the previous run and remember this one for the next
+    let mut changes = None;
+    if let Ok(location) = std::env::var("PREVIOUS_REPORT_LOCATION") {
+        let region_provider = RegionProviderChain::default_provider().or_else("us-east-1");
+        let config = aws_config::defaults(aws_config::BehaviorVersion::latest())
+            .region(region_provider)
+            .load()
+            .await;
+        let client = aws_sdk_s3::Client::new(&config);
+        // Notifications are already sent, so a failure here must not trigger a retry
+        match diff::load_previous(&client, &location).await {
+            Ok(Some(previous)) => {
+                let run_diff = diff::compare(
+                    &diff::failing_domains(&previous.statuses),
+                    &diff::failing_domains(&statuses),
+                );
+                changes = diff::render(&run_diff);
+            }
+            Ok(None) => {}
+            Err(err) => warn!("Cannot load previous report from {}: {}", location, err),
+        }
+        let current = Request {
+            statuses,
+            checked_at,
+            ..Default::default()
+        };
+        if let Err(err) = diff::save(&client, &location, &current).await {
+            warn!("Cannot save report to {}: {}", location, err);
+        }
+    }
 
     // Prepare the response
-    let resp = Response { report };
+    let resp = Response {
+        report,
+        severity,
+        stale,
+        full_report,
+        changes,
+        failures_by_issuer,
+    };
 
     // Return `Response` (it will be serialized to JSON automatically by the runtime)
     Ok(resp)
@@ -78,22 +377,44 @@ async fn main() -> Result<(), Error> {
 }
 
 #[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
 mod tests {
     use super::*;
+    use ssl_cert_monitor_common::RedirectTarget;
+
+    #[test]
+    fn test_is_stale() {
+        let now: DateTime<Utc> = "2024-05-01T12:00:00Z".parse().unwrap();
+        let max_age = chrono::Duration::minutes(60);
+        assert!(!is_stale(None, now, max_age));
+        assert!(!is_stale(Some(now), now, max_age));
+        assert!(!is_stale(
+            Some(now - chrono::Duration::minutes(60)),
+            now,
+            max_age
+        ));
+        assert!(is_stale(
+            Some(now - chrono::Duration::minutes(61)),
+            now,
+            max_age
+        ));
+    }
 
     #[test]
     fn test_aggregate_empty() {
-        let report = aggregate(vec![]).expect("should succeed");
+        let report = aggregate(&[], DEFAULT_MAX_LISTED).expect("should succeed");
         assert!(matches!(report, Report::Valid(())));
     }
 
     #[test]
     fn test_aggregate_one() {
-        let report = aggregate(vec![Status {
+        let report = aggregate(&[Status {
             domain: "foobar".into(),
             valid: false,
             error: "oops".into(),
-        }]).expect("should succeed");
+            suppressed: false,
+            ..Default::default()
+        }], DEFAULT_MAX_LISTED).expect("should succeed");
         match report {
             Report::Valid(_) => assert!(false),
             Report::Invalid(s) => assert_eq!(s, "Found 1 issues.\nDomain foobar (oops)")
@@ -102,22 +423,411 @@ mod tests {
 
     #[test]
     fn test_aggregate_mixed() {
-        let report = aggregate(vec![
+        let report = aggregate(&[
             Status {
                 domain: "foobar".into(),
                 valid: false,
                 error: "oops".into(),
+                suppressed: false,
+                ..Default::default()
             },
             Status {
                 domain: "baz".into(),
                 valid: true,
                 error: "".into(),
+                suppressed: false,
+                ..Default::default()
             },
-        ])
+        ], DEFAULT_MAX_LISTED)
         .expect("should succeed");
         match report {
             Report::Valid(_) => assert!(false),
             Report::Invalid(s) => assert_eq!(s, "Found 1 issues.\nDomain foobar (oops)")
         }
     }
+
+    #[test]
+    fn test_aggregate_suppressed() {
+        let report = aggregate(&[
+            Status {
+                domain: "foobar".into(),
+                valid: false,
+                error: "oops".into(),
+                suppressed: false,
+                ..Default::default()
+            },
+            Status {
+                domain: "legacy".into(),
+                valid: false,
+                error: "expired".into(),
+                suppressed: true,
+                ..Default::default()
+            },
+        ], DEFAULT_MAX_LISTED)
+        .expect("should succeed");
+        match report {
+            Report::Valid(_) => panic!("should be invalid"),
+            Report::Invalid(s) => assert_eq!(s, "Found 1 issues.\nDomain foobar (oops)")
+        }
+    }
+
+    #[test]
+    fn test_aggregate_only_suppressed() {
+        let report = aggregate(&[Status {
+            domain: "legacy".into(),
+            valid: false,
+            error: "expired".into(),
+            suppressed: true,
+            ..Default::default()
+        }], DEFAULT_MAX_LISTED)
+        .expect("should succeed");
+        assert!(matches!(report, Report::Valid(())));
+    }
+
+    #[test]
+    fn test_aggregate_expired() {
+        let expired = |domain: &str, hours| Status {
+            domain: domain.into(),
+            valid: false,
+            error: format!("certificate expired {} hours ago", hours),
+            suppressed: false,
+            expired_hours_ago: Some(hours),
+            ..Default::default()
+        };
+        let report = aggregate(&[expired("renewed", 2), expired("legacy", 2000)], DEFAULT_MAX_LISTED)
+            .expect("should succeed");
+        match report {
+            Report::Valid(_) => panic!("should be invalid"),
+            Report::Invalid(s) => assert_eq!(
+                s,
+                "Found 2 issues.\n\
+                 Domain renewed (certificate expired 2 hours ago [recently expired, renewal failed?])\n\
+                 Domain legacy (certificate expired 2000 hours ago [expired long ago, abandoned?])"
+            ),
+        }
+    }
+
+    #[test]
+    fn test_request_from_monitor_response() {
+        let response = ssl_cert_monitor_common::Response {
+            req_id: "42".into(),
+            statuses: vec![Status {
+                domain: "foobar".into(),
+                valid: false,
+                error: "certificate expires in 3 days".into(),
+                error_kind: Some("expired".into()),
+                days_remaining: Some(3),
+                ..Default::default()
+            }],
+            buckets: vec![("<7d".into(), 1)],
+            maintenance: false,
+            summary: None,
+            checked_at: None,
+            recommended_recheck_at: None,
+            stopped_early: false,
+            sink_errors: vec![],
+        };
+        let payload = serde_json::to_string(&response).expect("should serialize");
+        let request: Request = serde_json::from_str(&payload).expect("should deserialize");
+        assert_eq!(request.statuses, response.statuses);
+        assert!(matches!(aggregate(&request.statuses, DEFAULT_MAX_LISTED), Ok(Report::Invalid(_))));
+    }
+
+    #[test]
+    fn test_aggregate_collapsed() {
+        let mut statuses: Vec<Status> = (0..6)
+            .map(|i| Status {
+                domain: format!("d{}.com", i),
+                valid: false,
+                error: "network error: connection refused".into(),
+                error_kind: Some("network".into()),
+                ..Default::default()
+            })
+            .collect();
+        statuses.push(Status {
+            domain: "expiring.com".into(),
+            valid: false,
+            error: "certificate expires in 3 days".into(),
+            error_kind: Some("expired".into()),
+            ..Default::default()
+        });
+        let report = aggregate(&statuses, DEFAULT_MAX_LISTED).expect("should succeed");
+        match report {
+            Report::Valid(_) => panic!("should be invalid"),
+            Report::Invalid(s) => assert_eq!(
+                s,
+                "Found 7 issues.\n\
+                 6 domains: network error: connection refused — likely network outage\n\
+                 Domain expiring.com (certificate expires in 3 days)"
+            ),
+        }
+    }
+
+    #[test]
+    fn test_render_full() {
+        let statuses = [
+            Status {
+                domain: "later".into(),
+                valid: true,
+                days_remaining: Some(90),
+                ..Default::default()
+            },
+            Status {
+                domain: "down".into(),
+                valid: false,
+                error: "network error".into(),
+                ..Default::default()
+            },
+            Status {
+                domain: "soon".into(),
+                valid: false,
+                error: "certificate expires in 3 days".into(),
+                days_remaining: Some(3),
+                ..Default::default()
+            },
+            Status {
+                domain: "legacy".into(),
+                valid: false,
+                error: "certificate expired 30 hours ago".into(),
+                days_remaining: Some(-1),
+                suppressed: true,
+                ..Default::default()
+            },
+        ];
+        assert_eq!(
+            render_full(&statuses),
+            "Domain legacy: -1 days (suppressed: certificate expired 30 hours ago)\n\
+             Domain soon: 3 days (invalid: certificate expires in 3 days)\n\
+             Domain later: 90 days (valid)\n\
+             Domain down: unknown (invalid: network error)"
+        );
+    }
+
+    #[test]
+    fn test_response_without_full_report() {
+        let response = Response {
+            report: Report::Valid(()),
+            severity: Severity::Ok,
+            stale: false,
+            full_report: None,
+            changes: None,
+            failures_by_issuer: Vec::new(),
+        };
+        assert_eq!(
+            serde_json::to_string(&response).unwrap(),
+            r#"{"report":{"Valid":null},"severity":"ok","stale":false}"#
+        );
+    }
+
+    #[test]
+    fn test_failures_by_issuer() {
+        let status = |domain: &str, valid, issuer: Option<&str>| Status {
+            domain: domain.into(),
+            valid,
+            issuer: issuer.map(String::from),
+            ..Default::default()
+        };
+        let statuses = [
+            status("a.com", false, Some("R3")),
+            status("b.com", false, Some("Sectigo")),
+            status("c.com", false, Some("R3")),
+            status("d.com", false, None),
+            status("e.com", true, Some("R3")),
+            Status {
+                suppressed: true,
+                ..status("f.com", false, Some("Sectigo"))
+            },
+            status("g.com", false, Some("DigiCert")),
+        ];
+        let count = |issuer: &str, count| IssuerFailures {
+            issuer: issuer.into(),
+            count,
+        };
+        assert_eq!(
+            failures_by_issuer(&statuses),
+            vec![
+                count("R3", 2),
+                count("DigiCert", 1),
+                count("Sectigo", 1),
+                count(UNKNOWN_ISSUER, 1)
+            ]
+        );
+        assert!(failures_by_issuer(&statuses[4..5]).is_empty());
+
+        let response = serde_json::to_value(failures_by_issuer(&statuses[..1])).unwrap();
+        assert_eq!(response, serde_json::json!([{"issuer": "R3", "count": 1}]));
+    }
+
+    #[test]
+    fn test_aggregate_truncated() {
+        let statuses: Vec<Status> = (0..5)
+            .map(|i| Status {
+                domain: format!("d{}.com", i),
+                valid: false,
+                error: "oops".into(),
+                ..Default::default()
+            })
+            .collect();
+        match aggregate(&statuses, 2).expect("should succeed") {
+            Report::Valid(_) => panic!("should be invalid"),
+            Report::Invalid(s) => assert_eq!(
+                s,
+                "Found 5 issues.\n\
+                 Domain d0.com (oops)\n\
+                 Domain d1.com (oops)\n\
+                 ...and 3 more"
+            ),
+        }
+        match aggregate(&statuses, 5).expect("should succeed") {
+            Report::Valid(_) => panic!("should be invalid"),
+            Report::Invalid(s) => assert!(!s.contains("more")),
+        }
+        match aggregate(&statuses, 0).expect("should succeed") {
+            Report::Valid(_) => panic!("should be invalid"),
+            Report::Invalid(s) => assert_eq!(s, "Found 5 issues.\n...and 5 more"),
+        }
+    }
+
+    #[test]
+    fn test_severity_warning_kinds() {
+        let failed = |error_kind: &str| Status {
+            domain: "foobar".into(),
+            valid: false,
+            error_kind: Some(error_kind.into()),
+            ..Default::default()
+        };
+        let dns = failed("dns");
+        let warning_kinds = vec!["dns".to_string()];
+        assert_eq!(
+            severity(std::slice::from_ref(&dns), &[]),
+            Severity::Critical
+        );
+        assert_eq!(
+            severity(std::slice::from_ref(&dns), &warning_kinds),
+            Severity::Warning
+        );
+        assert_eq!(
+            severity(&[dns.clone(), failed("network")], &warning_kinds),
+            Severity::Critical
+        );
+        // Demoted failures are still reported as issues
+        match aggregate(std::slice::from_ref(&dns), 10).expect("should succeed") {
+            Report::Valid(_) => panic!("should be invalid"),
+            Report::Invalid(s) => assert!(s.starts_with("Found 1 issues."), "{}", s),
+        }
+    }
+
+    #[test]
+    fn test_aggregate_warning_only() {
+        let distrusted = Status {
+            domain: "legacy.com".into(),
+            valid: true,
+            distrusted_issuer: Some("Old CA".into()),
+            ..Default::default()
+        };
+        let statuses = std::slice::from_ref(&distrusted);
+        assert_eq!(severity(statuses, &[]), Severity::Warning);
+        match aggregate(statuses, DEFAULT_MAX_LISTED).expect("should succeed") {
+            Report::Valid(_) => panic!("should be invalid"),
+            Report::Invalid(s) => assert_eq!(
+                s,
+                "Found 1 issues.\nDomain legacy.com (issued by distrusted CA Old CA)"
+            ),
+        }
+    }
+
+    #[test]
+    fn test_describe() {
+        let distrusted = Status {
+            domain: "old-ca.com".into(),
+            valid: true,
+            distrusted_issuer: Some("Entrust".into()),
+            late_alert: Some("received fatal alert: InternalError".into()),
+            ..Default::default()
+        };
+        assert!(needs_attention(&distrusted));
+        assert_eq!(
+            describe(&distrusted),
+            "issued by distrusted CA Entrust, \
+             fatal TLS alert after handshake: received fatal alert: InternalError"
+        );
+        let long_lived = Status {
+            domain: "internal.com".into(),
+            valid: true,
+            validity_days: Some(3650),
+            validity_exceeded: true,
+            ..Default::default()
+        };
+        assert_eq!(describe(&long_lived), "valid for too long (3650 days)");
+        let rotated = Status {
+            domain: "rotated.com".into(),
+            valid: true,
+            rotated: true,
+            ..Default::default()
+        };
+        assert!(needs_attention(&rotated));
+        assert_eq!(describe(&rotated), "certificate rotated unexpectedly");
+        let redirected = Status {
+            domain: "example.com".into(),
+            valid: true,
+            redirects: vec![RedirectTarget {
+                target: "www.example.com:443".into(),
+                error: Some("certificate expires in 3 days".into()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(needs_attention(&redirected));
+        assert_eq!(
+            describe(&redirected),
+            "redirect target www.example.com:443: certificate expires in 3 days"
+        );
+
+        let healthy = Status {
+            domain: "healthy.com".into(),
+            valid: true,
+            ..Default::default()
+        };
+        assert!(!needs_attention(&healthy));
+        let suppressed = Status {
+            domain: "legacy.com".into(),
+            error: "oops".into(),
+            suppressed: true,
+            ..Default::default()
+        };
+        assert!(!needs_attention(&suppressed));
+        assert!(needs_attention(&Status {
+            suppressed: false,
+            ..suppressed
+        }));
+    }
+
+    #[test]
+    fn test_aggregate_tiers() {
+        let failed = |domain: &str, tier: Option<&str>| Status {
+            domain: domain.into(),
+            valid: false,
+            error: "oops".into(),
+            tier: tier.map(String::from),
+            ..Default::default()
+        };
+        let report = aggregate(&[
+            failed("plain", None),
+            failed("minor", Some("low")),
+            failed("main", Some("critical")),
+            failed("custom", Some("internal")),
+        ], DEFAULT_MAX_LISTED)
+        .expect("should succeed");
+        match report {
+            Report::Valid(_) => panic!("should be invalid"),
+            Report::Invalid(s) => assert_eq!(
+                s,
+                "Found 4 issues.\n\
+                 [critical] Domain main (oops)\n\
+                 [low] Domain minor (oops)\n\
+                 Domain plain (oops)\n\
+                 [internal] Domain custom (oops)"
+            ),
+        }
+    }
 }
@@ -0,0 +1,157 @@
+use crate::{describe, needs_attention, tier_label, tier_rank, truncate, Status};
+use lambda_runtime::tracing::info;
+use lambda_runtime::Error;
+use serde_json::{json, Value};
+use ssl_cert_monitor_common::common_failure;
+
+/// Build a Microsoft Teams message with an Adaptive Card listing failing domains
+/// and valid ones with warnings. Like in the report, at most `max_listed` domains
+/// are listed and failures sharing a common cause are collapsed into one line,
+/// keeping the card within the webhook payload limit.
+pub fn build_card(statuses: &[Status], max_listed: usize) -> Value {
+    let mut failed: Vec<&Status> = statuses
+        .iter()
+        .filter(|status| needs_attention(status))
+        .collect();
+    failed.sort_by_key(|status| tier_rank(status));
+    let failure = common_failure(statuses);
+    let (listed, remaining) = truncate(&failed, failure.as_ref(), max_listed);
+    let facts: Vec<Value> = listed
+        .into_iter()
+        .map(|status| {
+            json!({
+                "title": format!("{}{}", tier_label(status), status.domain),
+                "value": describe(status)
+            })
+        })
+        .collect();
+
+    let mut body = vec![json!({
+        "type": "TextBlock",
+        "text": format!("Found {} SSL certificate issues", failed.len()),
+        "weight": "Bolder",
+        "size": "Medium",
+        "wrap": true
+    })];
+    if let Some(failure) = failure {
+        body.push(json!({
+            "type": "TextBlock",
+            "text": failure.to_string(),
+            "wrap": true
+        }));
+    }
+    body.push(json!({
+        "type": "FactSet",
+        "facts": facts
+    }));
+    if remaining > 0 {
+        body.push(json!({
+            "type": "TextBlock",
+            "text": format!("...and {} more", remaining),
+            "wrap": true
+        }));
+    }
+
+    json!({
+        "type": "message",
+        "attachments": [{
+            "contentType": "application/vnd.microsoft.card.adaptive",
+            "content": {
+                "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                "type": "AdaptiveCard",
+                "version": "1.4",
+                "body": body
+            }
+        }]
+    })
+}
+
+/// Post a card to a Teams incoming webhook
+pub async fn notify(webhook_url: &str, card: &Value) -> Result<(), Error> {
+    reqwest::Client::new()
+        .post(webhook_url)
+        .json(card)
+        .send()
+        .await?
+        .error_for_status()?;
+    info!("Sent Teams notification");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_card() {
+        let card = build_card(
+            &[
+                Status {
+                    domain: "foobar".into(),
+                    valid: false,
+                    error: "oops".into(),
+                    suppressed: false,
+                    ..Default::default()
+                },
+                Status {
+                    domain: "baz".into(),
+                    valid: false,
+                    error: "expired".into(),
+                    suppressed: false,
+                    ..Default::default()
+                },
+                Status {
+                    domain: "healthy".into(),
+                    valid: true,
+                    error: "".into(),
+                    suppressed: false,
+                    ..Default::default()
+                },
+                Status {
+                    domain: "old-ca".into(),
+                    valid: true,
+                    distrusted_issuer: Some("Entrust".into()),
+                    ..Default::default()
+                },
+            ],
+            10,
+        );
+        let body = &card["attachments"][0]["content"]["body"];
+        assert_eq!(body[0]["text"], "Found 3 SSL certificate issues");
+        assert_eq!(
+            body[1]["facts"],
+            json!([
+                { "title": "foobar", "value": "oops" },
+                { "title": "baz", "value": "expired" },
+                { "title": "old-ca", "value": "issued by distrusted CA Entrust" }
+            ])
+        );
+    }
+
+    #[test]
+    fn test_build_card_truncated() {
+        let failed = |domain: String, kind: &str| Status {
+            domain,
+            valid: false,
+            error: format!("{} error", kind),
+            error_kind: Some(kind.into()),
+            ..Default::default()
+        };
+        let mut statuses: Vec<Status> = (0..8)
+            .map(|i| failed(format!("d{}.com", i), "network"))
+            .collect();
+        statuses.extend((0..2).map(|i| failed(format!("e{}.com", i), "expired")));
+        let card = build_card(&statuses, 1);
+        let body = &card["attachments"][0]["content"]["body"];
+        assert_eq!(body[0]["text"], "Found 10 SSL certificate issues");
+        assert_eq!(
+            body[1]["text"],
+            "8 domains: network error — likely network outage"
+        );
+        assert_eq!(
+            body[2]["facts"],
+            json!([{ "title": "e0.com", "value": "expired error" }])
+        );
+        assert_eq!(body[3]["text"], "...and 1 more");
+    }
+}